@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 use super::{response, HttpRequest, HttpResponse, HTTP_SERVICE};
-use crate::types::feeds::FeedStorage;
+use crate::types::{certified_data::CertifiedFeeds, feeds::FeedStorage};
 
 #[derive(Debug, PartialEq, Deserialize, Serialize, Validate)]
 struct GetAssetDataQueryParams {
@@ -18,6 +18,20 @@ impl TryFrom<String> for GetAssetDataQueryParams {
     }
 }
 
+#[derive(Debug, PartialEq, Deserialize, Serialize, Validate)]
+struct GetLatestAssetDataQueryParams {
+    id: String,
+    max_age: Option<u64>,
+}
+
+impl TryFrom<String> for GetLatestAssetDataQueryParams {
+    type Error = serde_qs::Error;
+
+    fn try_from(query: String) -> Result<Self, serde_qs::Error> {
+        serde_qs::from_str(&query)
+    }
+}
+
 pub async fn get_asset_data_request(req: HttpRequest) -> HttpResponse {
     let resp = _get_asset_data_request(req, false)
         .await
@@ -40,6 +54,20 @@ pub async fn get_asset_data_with_proof_request(req: HttpRequest) -> HttpResponse
     }
 }
 
+pub async fn get_latest_asset_data_request(req: HttpRequest) -> HttpResponse {
+    match _get_latest_asset_data_request(req).map_err(|e| e.to_string()) {
+        Ok(data) => response::ok(data),
+        Err(err) => response::bad_request(err),
+    }
+}
+
+pub async fn get_latest_certified_request(req: HttpRequest) -> HttpResponse {
+    match _get_latest_certified_request(req).map_err(|e| e.to_string()) {
+        Ok((data, certificate)) => response::certified(data, certificate),
+        Err(err) => response::bad_request(err),
+    }
+}
+
 pub async fn gather_metrics() -> HttpResponse {
     let data = crate::utils::metrics::gather_metrics();
 
@@ -63,3 +91,45 @@ async fn _get_asset_data_request(req: HttpRequest, with_signature: bool) -> Resu
 
     Ok(serde_json::to_vec(&rate)?)
 }
+
+#[inline(always)]
+fn _get_latest_asset_data_request(req: HttpRequest) -> Result<Vec<u8>> {
+    let service = HTTP_SERVICE.get().expect("State not initialized");
+    let query = service
+        .query_router
+        .inner
+        .at(&req.url)
+        .context("No route found")?
+        .params;
+
+    let params = GetLatestAssetDataQueryParams::try_from(query.to_string())?;
+    params.validate()?;
+
+    let latest = FeedStorage::latest(&params.id, params.max_age)?;
+
+    Ok(serde_json::to_vec(&latest)?)
+}
+
+/// Serves `/get_latest_certified/<id>`, the body is certified under the same path in `http_assets`
+#[inline(always)]
+fn _get_latest_certified_request(req: HttpRequest) -> Result<(Vec<u8>, String)> {
+    let service = HTTP_SERVICE.get().expect("State not initialized");
+    let id = service
+        .query_router
+        .inner
+        .at(&req.url)
+        .context("No route found")?
+        .params;
+
+    // the gateways look up the decoded path
+    let id = urlencoding::decode(&id)?;
+    let certified_response = CertifiedFeeds::get_http(&id)?;
+
+    let certificate = format!(
+        "certificate=:{}:, tree=:{}:",
+        base64::encode(certified_response.certificate),
+        base64::encode(certified_response.witness)
+    );
+
+    Ok((certified_response.body, certificate))
+}
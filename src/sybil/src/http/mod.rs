@@ -11,7 +11,10 @@ use ic_cdk::{query, update};
 
 use router::Router;
 
-use crate::types::http::{HttpRequest, HttpResponse};
+use crate::types::{
+    certified_data::CERTIFIED_HTTP_PATH,
+    http::{HttpRequest, HttpResponse},
+};
 
 pub static HTTP_SERVICE: OnceLock<HttpService> = OnceLock::new();
 
@@ -53,6 +56,20 @@ impl HttpService {
             )
             .expect("Failed to insert handler");
 
+        router
+            .insert(
+                "/get_latest_asset_data:query",
+                Box::new(|request| Box::pin(handlers::get_latest_asset_data_request(request))),
+            )
+            .expect("Failed to insert handler");
+
+        router
+            .insert(
+                &format!("{CERTIFIED_HTTP_PATH}:query"),
+                Box::new(|request| Box::pin(handlers::get_latest_certified_request(request))),
+            )
+            .expect("Failed to insert handler");
+
         let pre_middlewares: Vec<PreMiddleware> = vec![];
 
         let post_middlewares: Vec<PostMiddleware> = vec![];
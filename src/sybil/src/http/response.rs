@@ -12,6 +12,20 @@ pub fn ok(body: Vec<u8>) -> HttpResponse {
     }
 }
 
+/// Response with the IC certificate and the CBOR encoded witness of the body hash in `http_assets`
+#[inline(always)]
+pub fn certified(body: Vec<u8>, certificate: String) -> HttpResponse {
+    HttpResponse {
+        status_code: 200,
+        upgrade: Some(false),
+        headers: vec![
+            ("content-type".into(), "application/json".into()),
+            ("IC-Certificate".into(), certificate),
+        ],
+        body: serde_bytes::ByteBuf::from(body),
+    }
+}
+
 #[inline(always)]
 pub fn bad_request(msg: String) -> HttpResponse {
     let error = json!({
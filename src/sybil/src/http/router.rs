@@ -47,6 +47,7 @@ impl<H> Router<H> {
         Ok(())
     }
 
+    /// Routes ending with `/` match all the paths under them, the rest of the path is passed as the params
     pub fn at(&self, key: &str) -> Option<RouterMatch<H>> {
         let splitted_key = key.split('?').collect::<Vec<&str>>();
 
@@ -54,11 +55,23 @@ impl<H> Router<H> {
 
         let key = splitted_key.first().expect("invalid key").to_string();
 
-        let value = self.routes.get(&key)?;
+        if let Some(value) = self.routes.get(&key) {
+            return Some(RouterMatch {
+                params: params.to_string(),
+                value,
+            });
+        }
+
+        self.routes.iter().find_map(|(route, value)| {
+            let rest = key.strip_prefix(route.as_str())?;
+            if !route.ends_with('/') || rest.is_empty() {
+                return None;
+            }
 
-        Some(RouterMatch {
-            params: params.to_string(),
-            value,
+            Some(RouterMatch {
+                params: rest.to_string(),
+                value,
+            })
         })
     }
 }
@@ -80,4 +93,21 @@ mod tests {
         let route = router.at("/test?param=a").unwrap();
         println!("{:?}", route);
     }
+
+    #[test]
+    fn test_prefix_router() {
+        let mut router = Router::<String> {
+            routes: HashMap::new(),
+        };
+
+        router.insert("/test/:query", "test:1".to_string()).unwrap();
+
+        let route = router.at("/test/ETH%2FUSD").unwrap();
+        assert_eq!(route.params, "ETH%2FUSD");
+        assert_eq!(route.value, "test:1");
+
+        assert!(router.at("/test/").is_none());
+        assert!(router.at("/test").is_none());
+        assert!(router.at("/other/eth").is_none());
+    }
 }
@@ -1 +1,2 @@
 pub mod cache_cleaner;
+pub mod secrets_migration;
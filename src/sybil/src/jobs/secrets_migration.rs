@@ -0,0 +1,72 @@
+use thiserror::Error;
+
+use crate::{
+    log,
+    types::{
+        secrets::{SecretError, Secrets},
+        source::Source,
+    },
+    STATE,
+};
+
+#[derive(Error, Debug)]
+pub enum SecretsMigrationError {
+    #[error("Secret error: {0}")]
+    SecretError(#[from] SecretError),
+}
+
+/// Moves the plaintext api keys of the stored feeds into the owner secrets
+pub fn execute() {
+    ic_cdk::spawn(async {
+        if let Err(e) = _execute().await {
+            log!("[SECRETS MIGRATION] error while executing secrets migration job: {e:?}");
+        }
+    })
+}
+
+#[inline(always)]
+async fn _execute() -> Result<(), SecretsMigrationError> {
+    let feeds = STATE.with(|state| {
+        state
+            .borrow()
+            .feeds
+            .0
+            .values()
+            .filter_map(|feed| {
+                let sources = feed.new_sources.clone()?;
+                has_plaintext_api_keys(&sources)
+                    .then(|| (feed.id.clone(), feed.owner.clone(), sources))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    if feeds.is_empty() {
+        return Ok(());
+    }
+
+    log!("[SECRETS MIGRATION] secrets migration job started");
+    for (id, owner, mut sources) in feeds {
+        Secrets::store_api_keys(&owner, &id, &mut sources, false).await?;
+
+        STATE.with(|state| {
+            if let Some(feed) = state.borrow_mut().feeds.0.get_mut(&id) {
+                feed.new_sources = Some(sources);
+            }
+        });
+        log!("[SECRETS MIGRATION] api keys moved to the secrets. feed: {id}");
+    }
+    log!("[SECRETS MIGRATION] secrets migration job stopped");
+
+    Ok(())
+}
+
+fn has_plaintext_api_keys(sources: &[Source]) -> bool {
+    sources.iter().any(|source| match source {
+        Source::HttpSource(http_source) => http_source
+            .api_keys
+            .iter()
+            .flatten()
+            .any(|api_key| api_key.secret.is_none() && !api_key.key.is_empty()),
+        _ => false,
+    })
+}
@@ -19,13 +19,19 @@ use types::{
 };
 use utils::canister::set_custom_panic_hook;
 
-use crate::types::cache::{HttpCache, SignaturesCache};
+use crate::types::{
+    cache::{HttpCache, SignaturesCache},
+    certified_data::CertifiedFeeds,
+};
 
 thread_local! {
     pub static STATE: RefCell<State> = RefCell::default();
     pub static CACHE: RefCell<RateCache> = RefCell::default();
     pub static HTTP_CACHE: RefCell<HttpCache> = RefCell::default();
     pub static SIGNATURES_CACHE: RefCell<SignaturesCache> = RefCell::default();
+    pub static CERTIFIED_FEEDS: RefCell<CertifiedFeeds> = RefCell::default();
+    /// Derived on the first use, never saved to the stable memory
+    pub static SECRETS_KEY: RefCell<Option<[u8; 32]>> = RefCell::default();
 }
 
 #[query]
@@ -24,7 +24,7 @@ use crate::{
         canister, nat,
         siwe::{self, SiweError},
         validate_caller,
-        web3::{self, Web3Error, SUCCESSFUL_TX_STATUS},
+        web3::{Web3Error, SUCCESSFUL_TX_STATUS},
         CallerError,
     },
 };
@@ -90,7 +90,7 @@ async fn _deposit(tx_hash: String, msg: String, sig: String) -> Result<(), Depos
     let caller_eth = address::to_h160(&caller)?;
 
     let balances_cfg = state::get_cfg().balances_cfg;
-    let w3 = web3::instance(balances_cfg.rpc, clone_with_state!(evm_rpc_canister));
+    let w3 = balances_cfg.web3_instance();
 
     let tx_receipt = w3.get_tx_receipt(&tx_hash).await?;
 
@@ -227,7 +227,7 @@ async fn _withdraw(
 
     let cfg = state::get_cfg().balances_cfg;
 
-    let w3 = web3::instance(cfg.rpc, clone_with_state!(evm_rpc_canister));
+    let w3 = cfg.web3_instance();
     let contract_addr =
         Address::from_str(&cfg.erc20_contract).map_err(|_| AddressError::InvalidAddress)?;
 
@@ -272,7 +272,7 @@ async fn _withdraw_fees(to: String) -> Result<String, BalancesError> {
 
     let cfg = state::get_cfg().balances_cfg;
 
-    let w3 = web3::instance(cfg.rpc, clone_with_state!(evm_rpc_canister));
+    let w3 = cfg.web3_instance();
     let contract_addr =
         Address::from_str(&cfg.erc20_contract).map_err(|_| AddressError::InvalidAddress)?;
 
@@ -1,9 +1,11 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ic_cdk::{query, update};
 
 use crate::{
+    clone_with_state,
     types::{
         config::{Cfg, UpdateCfg},
+        source_health::{SourceHealth, SourcesHealth},
         state,
     },
     utils::validate_caller,
@@ -17,6 +19,16 @@ pub fn update_cfg(cfg: UpdateCfg) -> Result<(), String> {
 #[inline(always)]
 fn _update_cfg(cfg: UpdateCfg) -> Result<()> {
     validate_caller()?;
+    if cfg
+        .key_name
+        .as_ref()
+        .is_some_and(|key_name| key_name != &clone_with_state!(key_name))
+    {
+        return Err(anyhow!(
+            "key_name can only be changed with announce_key_rotation"
+        ));
+    }
+
     state::update(&cfg);
     Ok(())
 }
@@ -32,6 +44,18 @@ fn _get_cfg() -> Result<Cfg> {
     Ok(state::get_cfg())
 }
 
+/// Health of the sources across all the feeds, starting from the least healthy one
+#[query]
+pub fn get_source_health() -> Result<Vec<SourceHealth>, String> {
+    _get_source_health().map_err(|e| format!("{e:?}"))
+}
+
+#[inline(always)]
+fn _get_source_health() -> Result<Vec<SourceHealth>> {
+    validate_caller()?;
+    Ok(SourcesHealth::get_all())
+}
+
 #[update]
 pub fn clear_state() -> Result<(), String> {
     _clear_state().map_err(|e| format!("{e:?}"))
@@ -8,11 +8,17 @@ use validator::{Validate, ValidationErrors};
 use crate::log;
 use crate::metrics;
 use crate::types::feeds::FeedType;
-use crate::types::source::Source;
+use crate::types::signer::{SignatureScheme, Signer, SignerScope};
+use crate::types::source::{Source, SourceError};
 use crate::{
     types::{
-        feeds::{Feed, FeedError, FeedStorage},
+        balances::{BalanceError, Balances},
+        feeds::{Feed, FeedError, FeedStorage, RateResult},
+        rate_data::AssetData,
+        secrets::{SecretError, Secrets},
+        source_templates::{SourceTemplateError, SourceTemplates, TemplateSource},
         whitelist::{Whitelist, WhitelistError},
+        Address, Seconds,
     },
     utils::{
         siwe::{self, SiweError},
@@ -20,6 +26,9 @@ use crate::{
     },
 };
 
+/// Freshness of the cached responses used by `preview_source`
+const PREVIEW_EXPR_FREQ: Seconds = 60;
+
 #[derive(Error, Debug)]
 pub enum CustomFeedError {
     #[error("SIWE Error: {0}")]
@@ -36,6 +45,12 @@ pub enum CustomFeedError {
     FeedNotFound,
     #[error("Not feed owner")]
     NotFeedOwner,
+    #[error("Source template error: {0}")]
+    SourceTemplateError(#[from] SourceTemplateError),
+    #[error("Secret error: {0}")]
+    SecretError(#[from] SecretError),
+    #[error("Source error: {0}")]
+    SourceError(#[from] SourceError),
 }
 
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
@@ -47,10 +62,209 @@ pub struct CreateCustomFeedRequest {
     pub decimals: Option<u64>,
     #[validate(length(min = 1, max = 5))]
     pub sources: Vec<Source>,
+    /// Catalog templates, expanded into `sources` before the validation
+    pub templates: Option<Vec<TemplateSource>>,
+    pub signer_scope: Option<SignerScope>,
+    pub signature_scheme: Option<SignatureScheme>,
     pub msg: String,
     pub sig: String,
 }
 
+impl CreateCustomFeedRequest {
+    /// Appends the sources expanded from the templates
+    fn expand_templates(&mut self) -> Result<(), SourceTemplateError> {
+        for template_source in self.templates.take().into_iter().flatten() {
+            let source = SourceTemplates::expand(&template_source)?;
+            self.sources.push(Source::HttpSource(source));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PreviewSourceRequest {
+    pub source: Source,
+    pub msg: String,
+    pub sig: String,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PreviewError {
+    pub kind: String,
+    pub message: String,
+}
+
+impl From<&SourceError> for PreviewError {
+    fn from(err: &SourceError) -> Self {
+        Self {
+            kind: err.kind().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct SourcePreview {
+    /// Resolved value encoded as JSON
+    pub value: Option<String>,
+    /// Beginning of the raw response, for the HTTP sources only
+    pub excerpt: Option<String>,
+    pub bytes: u64,
+    pub cached_at: Seconds,
+    pub error: Option<PreviewError>,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct FeedPreview {
+    pub sources: Vec<SourcePreview>,
+    /// Aggregated value, missing if any of the sources has failed
+    pub data: Option<AssetData>,
+    /// Fee charged for the fetched bytes
+    pub fee: Nat,
+    pub error: Option<PreviewError>,
+}
+
+impl From<&Result<RateResult, SourceError>> for SourcePreview {
+    fn from(result: &Result<RateResult, SourceError>) -> Self {
+        match result {
+            Ok(rate) => Self {
+                value: Some(rate.rate.to_string()),
+                excerpt: rate.excerpt.clone(),
+                bytes: rate.bytes as u64,
+                cached_at: rate.cached_at,
+                error: None,
+            },
+            Err(err) => Self {
+                excerpt: match err {
+                    SourceError::UnresolvedResponse { excerpt, .. } => Some(excerpt.clone()),
+                    _ => None,
+                },
+                bytes: err.fetched_bytes() as u64,
+                error: Some(PreviewError::from(err)),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Previews are charged as the feed reads, so the caller has to have a balance
+async fn recover_previewer(msg: &str, sig: &str) -> Result<Address, CustomFeedError> {
+    let addr = recover_whitelisted(msg, sig).await?;
+    if !Balances::contains(&addr) {
+        return Err(FeedError::from(BalanceError::BalanceDoesNotExist))?;
+    }
+
+    Ok(addr)
+}
+
+/// Bytes fetched by the sources, including the responses which couldn't be resolved
+fn fetched_bytes(results: &[Result<RateResult, SourceError>]) -> usize {
+    results
+        .iter()
+        .map(|result| match result {
+            Ok(result) => result.bytes,
+            Err(err) => err.fetched_bytes(),
+        })
+        .sum()
+}
+
+async fn recover_whitelisted(msg: &str, sig: &str) -> Result<Address, CustomFeedError> {
+    let addr = siwe::recover(msg, sig).await?;
+    if !Whitelist::contains(&addr) {
+        return Err(WhitelistError::AddressNotWhitelisted.into());
+    }
+
+    Ok(addr)
+}
+
+/// Fetches the source with the caller secrets, nothing is stored
+/// and the fee of the fetched bytes is charged
+#[update]
+pub async fn preview_source(req: PreviewSourceRequest) -> Result<SourcePreview, String> {
+    _preview_source(req)
+        .await
+        .map_err(|e| format!("Failed to preview source: {e}"))
+}
+
+pub async fn _preview_source(req: PreviewSourceRequest) -> Result<SourcePreview, CustomFeedError> {
+    let addr = recover_previewer(&req.msg, &req.sig).await?;
+
+    let mut feed = Feed {
+        update_freq: PREVIEW_EXPR_FREQ,
+        ..Default::default()
+    };
+    feed.set_owner(addr.clone());
+
+    let results = FeedStorage::fetch_rates(&feed, &[req.source], false).await;
+    let bytes = fetched_bytes(&results);
+    if bytes > 0 {
+        FeedStorage::charge_bytes(&addr, bytes).await?;
+    }
+
+    Ok(SourcePreview::from(&results[0]))
+}
+
+/// Runs the sources and the aggregation of the feed as `create_custom_feed` would,
+/// nothing is stored and the fee of the fetched bytes is charged
+#[update]
+pub async fn preview_feed(req: CreateCustomFeedRequest) -> Result<FeedPreview, String> {
+    _preview_feed(req)
+        .await
+        .map_err(|e| format!("Failed to preview feed: {e}"))
+}
+
+pub async fn _preview_feed(
+    mut req: CreateCustomFeedRequest,
+) -> Result<FeedPreview, CustomFeedError> {
+    req.id = format!("custom_{}", req.id);
+
+    let addr = recover_previewer(&req.msg, &req.sig).await?;
+
+    req.expand_templates()?;
+    req.validate()?;
+    for source in &req.sources {
+        source.validate()?;
+    }
+
+    let mut feed = Feed::from(req.clone());
+    feed.set_owner(addr.clone());
+
+    let fetched = FeedStorage::fetch_rates(&feed, &req.sources, false).await;
+    let sources = fetched.iter().map(SourcePreview::from).collect();
+    let fee = FeedStorage::charge_bytes(&addr, fetched_bytes(&fetched)).await?;
+    let results = fetched
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+
+    let mut preview = FeedPreview {
+        fee,
+        sources,
+        ..Default::default()
+    };
+
+    if results.len() < req.sources.len() {
+        preview.error = Some(PreviewError {
+            kind: "SourceError".to_string(),
+            message: "some of the sources have failed".to_string(),
+        });
+        return Ok(preview);
+    }
+
+    match FeedStorage::aggregate(&feed, &results) {
+        Ok(result) => preview.data = Some(result.data),
+        Err(err) => {
+            preview.error = Some(PreviewError {
+                kind: "AggregationError".to_string(),
+                message: err.to_string(),
+            })
+        }
+    }
+
+    Ok(preview)
+}
+
 #[update]
 pub async fn create_custom_feed(req: CreateCustomFeedRequest) -> Result<(), String> {
     _create_custom_feed(req)
@@ -61,21 +275,34 @@ pub async fn create_custom_feed(req: CreateCustomFeedRequest) -> Result<(), Stri
 pub async fn _create_custom_feed(mut req: CreateCustomFeedRequest) -> Result<(), CustomFeedError> {
     req.id = format!("custom_{}", req.id);
 
-    let addr = siwe::recover(&req.msg, &req.sig).await?;
-    if !Whitelist::contains(&addr) {
-        return Err(WhitelistError::AddressNotWhitelisted.into());
-    }
+    let addr = recover_whitelisted(&req.msg, &req.sig).await?;
 
     if FeedStorage::contains(&req.id) {
         return Err(CustomFeedError::FeedAlreadyExists)?;
     }
 
+    req.expand_templates()?;
     req.validate()?;
+    for source in &req.sources {
+        source.validate()?;
+    }
 
     let mut feed = Feed::from(req.clone());
     feed.set_owner(addr.clone());
 
     FeedStorage::get_custom_rate(&feed, &req.sources).await?;
+
+    // derive the signer key in advance, so it can be obtained with a query
+    Signer::for_feed(&feed)
+        .derive()
+        .await
+        .map_err(FeedError::from)?;
+
+    // plaintext api keys are kept in the owner secrets only
+    if let Some(sources) = feed.new_sources.as_mut() {
+        Secrets::store_api_keys(&addr, &feed.id, sources, true).await?;
+    }
+
     FeedStorage::add(feed);
 
     metrics!(inc CUSTOM_FEEDS);
@@ -101,10 +328,7 @@ pub async fn _remove_custom_feed(
     msg: String,
     sig: String,
 ) -> Result<(), CustomFeedError> {
-    let addr = siwe::recover(&msg, &sig).await?;
-    if !Whitelist::contains(&addr) {
-        return Err(WhitelistError::AddressNotWhitelisted.into());
-    }
+    let addr = recover_whitelisted(&msg, &sig).await?;
 
     if let Some(feed) = FeedStorage::get(&id) {
         if feed.owner != addr {
@@ -112,6 +336,9 @@ pub async fn _remove_custom_feed(
         }
 
         FeedStorage::remove(&id);
+        if let Some(sources) = &feed.new_sources {
+            Secrets::remove_api_keys(&addr, &id, sources);
+        }
 
         metrics!(dec CUSTOM_FEEDS);
         log!("[FEEDS] custom feed removed. id: {}, owner: {}", id, addr);
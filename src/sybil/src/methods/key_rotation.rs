@@ -0,0 +1,164 @@
+use std::str::FromStr;
+
+use candid::Nat;
+use ic_cdk::{query, update};
+use ic_web3_rs::{contract::Contract, ethabi::Address};
+use thiserror::Error;
+
+use super::balances::TOKEN_ABI;
+use crate::{
+    clone_with_state, log,
+    types::{
+        key_rotation::{KeyRotation, KeyRotationError, PendingKey, SignerAddresses},
+        state,
+    },
+    utils::{
+        address::{self, AddressError},
+        canister, nat, validate_caller,
+        web3::Web3Error,
+        CallerError,
+    },
+};
+
+#[derive(Error, Debug)]
+pub enum KeyRotationRequestError {
+    #[error("Key rotation error: {0}")]
+    KeyRotation(#[from] KeyRotationError),
+    #[error("Caller error: {0}")]
+    Caller(#[from] CallerError),
+    #[error("Address error: {0}")]
+    Address(#[from] AddressError),
+    #[error("Web3 error: {0}")]
+    Web3(#[from] Web3Error),
+    #[error("Failed to get contract from json: {0}")]
+    Contract(String),
+    #[error("Canister error: {0}")]
+    Canister(#[from] canister::CanisterError),
+}
+
+#[update]
+pub async fn announce_key_rotation(
+    key_name: String,
+    grace_period: Nat,
+) -> Result<PendingKey, String> {
+    _announce_key_rotation(key_name, grace_period)
+        .await
+        .map_err(|e| format!("failed to announce key rotation: {e}"))
+}
+
+#[inline(always)]
+async fn _announce_key_rotation(
+    key_name: String,
+    grace_period: Nat,
+) -> Result<PendingKey, KeyRotationRequestError> {
+    validate_caller()?;
+
+    let pending_key = KeyRotation::announce(key_name, nat::to_u64(&grace_period)).await?;
+
+    log!(
+        "[KEY ROTATION] new key announced. key name: {}, address: {}",
+        pending_key.key_name,
+        pending_key.eth_address
+    );
+    Ok(pending_key)
+}
+
+/// Moves the tokens held by the active address to the announced one and switches the keys,
+/// once the migration transaction is confirmed. The native balance left on the retired address
+/// is reported in `get_signer_addresses`.
+/// Returns the hash of the migration transaction, if there was anything to migrate.
+#[update]
+pub async fn complete_key_rotation() -> Result<Option<String>, String> {
+    _complete_key_rotation()
+        .await
+        .map_err(|e| format!("failed to complete key rotation: {e}"))
+}
+
+#[inline(always)]
+async fn _complete_key_rotation() -> Result<Option<String>, KeyRotationRequestError> {
+    validate_caller()?;
+    let pending_key = KeyRotation::start_migration()?;
+
+    let result = async {
+        let (tx_hash, native_balance_left) = migrate_funds(&pending_key).await?;
+        let address = KeyRotation::complete(native_balance_left.clone()).await?;
+
+        Ok::<_, KeyRotationRequestError>((address, tx_hash, native_balance_left))
+    }
+    .await;
+
+    let (address, tx_hash, native_balance_left) = result.map_err(|err| {
+        KeyRotation::abort_migration();
+        err
+    })?;
+
+    log!(
+        "[KEY ROTATION] key rotation completed. new address: {}, migration tx: {:?}, native balance left: {}",
+        address,
+        tx_hash,
+        native_balance_left
+    );
+    Ok(tx_hash)
+}
+
+/// Transfers the on-chain token balance of the active address, so a repeated migration moves only what's left.
+/// Returns the confirmed transaction hash and the native balance left on the active address
+async fn migrate_funds(
+    pending_key: &PendingKey,
+) -> Result<(Option<String>, Nat), KeyRotationRequestError> {
+    let cfg = state::get_cfg().balances_cfg;
+
+    let w3 = cfg.web3_instance();
+    let contract_addr =
+        Address::from_str(&cfg.erc20_contract).map_err(|_| AddressError::InvalidAddress)?;
+    let sybil_addr = canister::eth_address().await?;
+
+    // all the deposits and fees are held by the active address
+    let balance = w3
+        .get_erc20_balance(contract_addr, address::to_h160(&sybil_addr)?, None)
+        .await?;
+
+    let tx_hash = if !balance.is_zero() {
+        let contract = Contract::from_json(w3.eth(), contract_addr, TOKEN_ABI)
+            .map_err(|err| KeyRotationRequestError::Contract(err.to_string()))?;
+
+        let tx_hash = w3
+            .send_erc20(
+                &contract,
+                &nat::from_u256(&balance),
+                &pending_key.eth_address,
+                sybil_addr.clone(),
+                clone_with_state!(key_name),
+                nat::to_u64(&cfg.chain_id),
+            )
+            .await?;
+
+        w3.wait_for_success(&tx_hash).await?;
+        Some(tx_hash)
+    } else {
+        None
+    };
+
+    let native_balance_left = w3.get_balance(address::to_h160(&sybil_addr)?, None).await?;
+
+    Ok((tx_hash, nat::from_u256(&native_balance_left)))
+}
+
+#[update]
+pub fn cancel_key_rotation() -> Result<(), String> {
+    _cancel_key_rotation().map_err(|e| format!("failed to cancel key rotation: {e}"))
+}
+
+#[inline(always)]
+fn _cancel_key_rotation() -> Result<(), KeyRotationRequestError> {
+    validate_caller()?;
+    KeyRotation::cancel()?;
+
+    log!("[KEY ROTATION] key rotation cancelled");
+    Ok(())
+}
+
+#[query]
+pub fn get_signer_addresses() -> SignerAddresses {
+    KeyRotation::addresses()
+}
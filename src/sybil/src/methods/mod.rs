@@ -2,7 +2,10 @@ pub mod balances;
 pub mod controllers;
 pub mod custom_feeds;
 pub mod default_feeds;
+pub mod key_rotation;
+pub mod secrets;
 pub mod signatures;
+pub mod source_templates;
 pub mod transforms;
 pub mod whitelist;
 
@@ -18,7 +21,8 @@ use ic_utils::{
 use crate::{
     metrics,
     types::{
-        feeds::{Feed, FeedError, FeedStorage, GetFeedsFilter},
+        certified_data::{CertifiedAssetData, CertifiedDataError, CertifiedFeeds},
+        feeds::{Feed, FeedError, FeedStorage, GetFeedsFilter, LatestAssetData},
         pagination::{Pagination, PaginationResult},
         rate_data::AssetDataResult,
     },
@@ -31,6 +35,8 @@ pub enum AssetsError {
     FeedError(#[from] FeedError),
     #[error("Siwe error: {0}")]
     SiweError(#[from] siwe::SiweError),
+    #[error("Certified data error: {0}")]
+    CertifiedDataError(#[from] CertifiedDataError),
 }
 
 #[query]
@@ -134,11 +140,39 @@ async fn _get_asset_data(id: String) -> Result<AssetDataResult, AssetsError> {
     let mut rate = FeedStorage::rate(&id, false).await?;
 
     rate.signature = None;
+    rate.next_signature = None;
 
     metrics!(inc SUCCESSFUL_GET_ASSET_DATA_CALLS, id);
     Ok(rate)
 }
 
+/// Returns the last stored value of the feed with its age, without fetching the sources
+#[query]
+pub fn get_latest_asset_data(id: String, max_age: Option<u64>) -> Result<LatestAssetData, String> {
+    _get_latest_asset_data(id, max_age)
+        .map_err(|e| format!("failed to get latest asset data: {}", e))
+}
+
+#[inline(always)]
+fn _get_latest_asset_data(
+    id: String,
+    max_age: Option<u64>,
+) -> Result<LatestAssetData, AssetsError> {
+    Ok(FeedStorage::latest(&id, max_age)?)
+}
+
+/// Returns the latest stored value of the feed without fetching it,
+/// along with the certificate and witness to verify it against the subnet public key
+#[query]
+pub fn get_latest_certified(id: String) -> Result<CertifiedAssetData, String> {
+    _get_latest_certified(id).map_err(|e| format!("failed to get latest certified data: {}", e))
+}
+
+#[inline(always)]
+fn _get_latest_certified(id: String) -> Result<CertifiedAssetData, AssetsError> {
+    Ok(CertifiedFeeds::get(&id)?)
+}
+
 #[query(name = "getCanistergeekInformation")]
 pub async fn get_canistergeek_information(
     request: GetInformationRequest,
@@ -0,0 +1,88 @@
+use ic_cdk::update;
+
+use thiserror::Error;
+
+use crate::{
+    log,
+    types::{
+        secrets::{SecretError, SecretInfo, Secrets},
+        whitelist::{Whitelist, WhitelistError},
+    },
+    utils::siwe::{self, SiweError},
+};
+
+#[derive(Error, Debug)]
+pub enum SecretsRequestError {
+    #[error("SIWE Error: {0}")]
+    SIWEError(#[from] SiweError),
+    #[error("Whitelist Error: {0}")]
+    WhitelistError(#[from] WhitelistError),
+    #[error("Secret Error: {0}")]
+    SecretError(#[from] SecretError),
+}
+
+/// Creates or rotates the secret of the caller, sources referencing it pick up the new value
+#[update]
+pub async fn set_secret(
+    name: String,
+    value: String,
+    msg: String,
+    sig: String,
+) -> Result<(), String> {
+    _set_secret(name, value, msg, sig)
+        .await
+        .map_err(|e| format!("failed to set secret: {e}"))
+}
+
+#[inline(always)]
+async fn _set_secret(
+    name: String,
+    value: String,
+    msg: String,
+    sig: String,
+) -> Result<(), SecretsRequestError> {
+    let addr = siwe::recover(&msg, &sig).await?;
+    if !Whitelist::contains(&addr) {
+        return Err(WhitelistError::AddressNotWhitelisted.into());
+    }
+
+    Secrets::set(&addr, &name, &value).await?;
+
+    log!("[SECRETS] secret set. name: {}, owner: {}", name, addr);
+    Ok(())
+}
+
+#[update]
+pub async fn remove_secret(name: String, msg: String, sig: String) -> Result<(), String> {
+    _remove_secret(name, msg, sig)
+        .await
+        .map_err(|e| format!("failed to remove secret: {e}"))
+}
+
+#[inline(always)]
+async fn _remove_secret(name: String, msg: String, sig: String) -> Result<(), SecretsRequestError> {
+    let addr = siwe::recover(&msg, &sig).await?;
+    if !Whitelist::contains(&addr) {
+        return Err(WhitelistError::AddressNotWhitelisted.into());
+    }
+
+    Secrets::remove(&addr, &name)?;
+
+    log!("[SECRETS] secret removed. name: {}, owner: {}", name, addr);
+    Ok(())
+}
+
+/// Returns the names of the caller secrets, the values are never returned
+#[update]
+pub async fn get_secrets(msg: String, sig: String) -> Result<Vec<SecretInfo>, String> {
+    _get_secrets(msg, sig)
+        .await
+        .map_err(|e| format!("failed to get secrets: {e}"))
+}
+
+#[inline(always)]
+async fn _get_secrets(msg: String, sig: String) -> Result<Vec<SecretInfo>, SecretsRequestError> {
+    let addr = siwe::recover(&msg, &sig).await?;
+
+    Ok(Secrets::list(&addr))
+}
@@ -1,6 +1,13 @@
-use crate::{utils::validate_caller, SIGNATURES_CACHE};
+use crate::{
+    types::{
+        balances::Balances,
+        signer::{SignatureScheme, Signer, SignerError, SignerRequest},
+    },
+    utils::{address, validate_caller},
+    SIGNATURES_CACHE,
+};
 use anyhow::Result;
-use ic_cdk::update;
+use ic_cdk::{query, update};
 
 #[update]
 async fn sign_message(message: String) -> Result<String, String> {
@@ -13,9 +20,66 @@ async fn sign_message(message: String) -> Result<String, String> {
 async fn _sign_message(message: String) -> Result<String> {
     validate_caller()?;
     let mut cache = SIGNATURES_CACHE.with(|c| c.borrow().clone());
-    let signature = cache.eth_sign(message.as_bytes()).await?;
+    let signature = cache
+        .eth_sign(message.as_bytes(), &Signer::canister())
+        .await?;
 
     let signature = hex::encode(signature);
 
     Ok(signature)
 }
+
+#[query]
+fn get_signer_address(req: SignerRequest) -> Result<String, String> {
+    _get_signer_address(req).map_err(|e| format!("Failed to get signer address: {}", e))
+}
+
+#[inline]
+fn _get_signer_address(req: SignerRequest) -> Result<String, SignerError> {
+    Signer::for_request(&req)?
+        .cached_eth_address()
+        .ok_or(SignerError::AddressNotDerived)
+}
+
+#[query]
+fn get_signer_public_key(req: SignerRequest, scheme: SignatureScheme) -> Result<String, String> {
+    _get_signer_public_key(req, scheme)
+        .map_err(|e| format!("Failed to get signer public key: {}", e))
+}
+
+#[inline]
+fn _get_signer_public_key(
+    req: SignerRequest,
+    scheme: SignatureScheme,
+) -> Result<String, SignerError> {
+    Signer::for_request(&req)?
+        .with_scheme(scheme)
+        .cached_public_key()
+        .ok_or(SignerError::AddressNotDerived)
+}
+
+/// Derives the signer key with the scheme, so it can be obtained with `get_signer_public_key`
+/// and `get_signer_address`. Returns the hex encoded public key
+#[update]
+async fn derive_signer_key(req: SignerRequest, scheme: SignatureScheme) -> Result<String, String> {
+    _derive_signer_key(req, scheme)
+        .await
+        .map_err(|e| format!("Failed to derive signer key: {}", e))
+}
+
+#[inline]
+async fn _derive_signer_key(req: SignerRequest, scheme: SignatureScheme) -> Result<String> {
+    // the keys are derived for the known owners only, so the cache can't be flooded
+    if let SignerRequest::Owner(owner) = &req {
+        if !Balances::contains(&address::from_str(owner)?) {
+            return Err(SignerError::UnknownOwner)?;
+        }
+    }
+
+    let public_key = Signer::for_request(&req)?
+        .with_scheme(scheme)
+        .derive()
+        .await?;
+
+    Ok(public_key)
+}
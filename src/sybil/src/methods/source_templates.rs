@@ -0,0 +1,53 @@
+use ic_cdk::{query, update};
+
+use thiserror::Error;
+
+use crate::{
+    log,
+    types::source_templates::{SourceTemplate, SourceTemplateError, SourceTemplates},
+    utils::{validate_caller, CallerError},
+};
+
+#[derive(Error, Debug)]
+pub enum SourceTemplatesRequestsError {
+    #[error("Source template error: {0}")]
+    SourceTemplate(#[from] SourceTemplateError),
+    #[error("Caller error: {0}")]
+    Caller(#[from] CallerError),
+}
+
+/// Adds the template to the catalog or replaces the one with the same name
+#[update]
+pub fn set_source_template(template: SourceTemplate) -> Result<(), String> {
+    _set_source_template(template).map_err(|e| format!("failed to set a source template: {e}"))
+}
+
+#[inline(always)]
+fn _set_source_template(template: SourceTemplate) -> Result<(), SourceTemplatesRequestsError> {
+    validate_caller()?;
+
+    let name = template.name.clone();
+    SourceTemplates::set(template)?;
+
+    log!("[TEMPLATES] source template set. Name: {name}");
+    Ok(())
+}
+
+#[update]
+pub fn remove_source_template(name: String) -> Result<(), String> {
+    _remove_source_template(name).map_err(|e| format!("failed to remove a source template: {e}"))
+}
+
+#[inline(always)]
+fn _remove_source_template(name: String) -> Result<(), SourceTemplatesRequestsError> {
+    validate_caller()?;
+    SourceTemplates::remove(&name)?;
+
+    log!("[TEMPLATES] source template removed. Name: {name}");
+    Ok(())
+}
+
+#[query]
+pub fn get_source_templates() -> Vec<SourceTemplate> {
+    SourceTemplates::get_all()
+}
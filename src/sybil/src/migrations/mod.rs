@@ -1,6 +1,6 @@
 #![allow(deprecated)]
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use candid::{CandidType, Nat, Principal};
 use ic_cdk::{post_upgrade, pre_upgrade, storage};
@@ -9,13 +9,23 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     http::HttpService,
+    jobs::secrets_migration,
     log, metrics,
     types::{
         balances::{Balances, BalancesCfg},
         cache::{HttpCache, RateCache, SignaturesCache},
+        certified_data::CertifiedFeeds,
         feeds::{Feed, FeedStatus, FeedStorage, FeedType},
+        key_rotation::{PendingKey, RetiredKey},
+        logs_index::LogsIndexes,
         rate_data::AssetDataResult,
+        rpc::RpcTransport,
+        rpc_wrapper::RpcWrappers,
+        secrets::Secrets,
+        signer::{SignatureScheme, SignerScope},
         source::{HttpSource, Source},
+        source_health::SourcesHealth,
+        source_templates::SourceTemplates,
         state::State,
         whitelist::Whitelist,
         Address, Seconds, Timestamp,
@@ -74,6 +84,7 @@ impl From<OldFeed> for Feed {
                                 api_keys: s.api_keys,
                                 resolver: s.resolver,
                                 expected_bytes: s.expected_bytes,
+                                ..Default::default()
                             })
                         })
                         .collect(),
@@ -85,6 +96,8 @@ impl From<OldFeed> for Feed {
             status: old.status.into(),
             owner: old.owner,
             data: old.data,
+            signer_scope: old.signer_scope,
+            signature_scheme: old.signature_scheme,
         }
     }
 }
@@ -100,6 +113,8 @@ pub struct OldFeed {
     pub status: FeedStatus,
     pub owner: Address,
     pub data: Option<AssetDataResult>,
+    pub signer_scope: Option<SignerScope>,
+    pub signature_scheme: Option<SignatureScheme>,
 }
 
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
@@ -107,6 +122,8 @@ pub struct OldFeedStatus {
     last_update: Timestamp,
     updated_counter: u64,
     requests_counter: u64,
+    data_timestamp: Option<Timestamp>,
+    skipped_sources: Option<u64>,
 }
 
 impl From<OldFeedStatus> for FeedStatus {
@@ -115,6 +132,8 @@ impl From<OldFeedStatus> for FeedStatus {
             last_update: old.last_update,
             updated_counter: old.updated_counter,
             requests_counter: old.requests_counter,
+            data_timestamp: old.data_timestamp,
+            skipped_sources: old.skipped_sources,
         }
     }
 }
@@ -145,7 +164,20 @@ pub struct OldState {
     pub balances: Balances,
     pub balances_cfg: BalancesCfg,
     pub eth_address: Option<Address>,
+    pub signer_addresses: Option<HashMap<String, Address>>,
+    pub signer_public_keys: Option<HashMap<String, String>>,
+    pub next_key: Option<PendingKey>,
+    pub previous_key: Option<RetiredKey>,
     pub whitelist: Whitelist,
+    pub secrets: Option<Secrets>,
+    pub logs_indexes: Option<LogsIndexes>,
+    pub canister_call_canisters: Option<Vec<Principal>>,
+    pub canister_call_methods: Option<Vec<String>>,
+    pub bitcoin_canister: Option<Principal>,
+    pub rpc_transport: Option<RpcTransport>,
+    pub rpc_wrappers: Option<RpcWrappers>,
+    pub source_templates: Option<SourceTemplates>,
+    pub sources_health: Option<SourcesHealth>,
     pub data_fetchers: Option<DataFetchersStorage>,
     pub data_fetchers_indexer: Option<DataFethcersIndexer>,
 }
@@ -167,7 +199,20 @@ impl From<OldState> for State {
             balances: state.balances,
             balances_cfg: state.balances_cfg,
             eth_address: state.eth_address,
+            signer_addresses: state.signer_addresses.unwrap_or_default(),
+            signer_public_keys: state.signer_public_keys.unwrap_or_default(),
+            next_key: state.next_key,
+            previous_key: state.previous_key,
             whitelist: state.whitelist,
+            secrets: state.secrets.unwrap_or_default(),
+            logs_indexes: state.logs_indexes.unwrap_or_default(),
+            canister_call_canisters: state.canister_call_canisters.unwrap_or_default(),
+            canister_call_methods: state.canister_call_methods,
+            bitcoin_canister: state.bitcoin_canister,
+            rpc_transport: state.rpc_transport,
+            rpc_wrappers: state.rpc_wrappers.unwrap_or_default(),
+            source_templates: state.source_templates.unwrap_or_default(),
+            sources_health: state.sources_health.unwrap_or_default(),
         }
     }
 }
@@ -286,6 +331,11 @@ fn post_upgrade() {
         });
     }
 
+    CertifiedFeeds::rebuild();
+
+    // the secrets key can't be derived during the upgrade
+    ic_cdk_timers::set_timer(Duration::ZERO, secrets_migration::execute);
+
     log!("Post upgrade finished");
 
     HttpService::init();
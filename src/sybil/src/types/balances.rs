@@ -6,9 +6,17 @@ use thiserror::Error;
 use candid::{CandidType, Nat};
 use ic_web3_rs::ethabi::Error as EthabiError;
 
-use super::{whitelist::WhitelistError, Address};
+use super::{rpc::RpcCfg, whitelist::WhitelistError, Address};
 use crate::{
-    utils::{address::AddressError, canister::CanisterError, siwe::SiweError, web3::Web3Error},
+    clone_with_state,
+    utils::{
+        address::AddressError,
+        canister::CanisterError,
+        nat,
+        retry::{Attempts, RetryPolicy},
+        siwe::SiweError,
+        web3::{self, MultiProviderTransport, Web3Error, Web3Instance},
+    },
     STATE,
 };
 
@@ -18,6 +26,23 @@ pub struct BalancesCfg {
     pub chain_id: Nat,
     pub erc20_contract: Address,
     pub fee_per_byte: Nat,
+    /// Other providers and the consensus policy for the balances rpc, the chain id is taken from `chain_id`
+    pub rpc_cfg: Option<RpcCfg>,
+}
+
+impl BalancesCfg {
+    pub fn web3_instance(&self) -> Web3Instance<MultiProviderTransport> {
+        let mut rpc_cfg = self.rpc_cfg.clone().unwrap_or_default();
+        rpc_cfg.chain_id = Some(nat::to_u64(&self.chain_id));
+
+        web3::instance(
+            rpc_cfg.urls(&self.rpc),
+            &rpc_cfg,
+            clone_with_state!(evm_rpc_canister),
+            RetryPolicy::default(),
+            Attempts::unlimited(),
+        )
+    }
 }
 
 #[derive(Error, Debug)]
@@ -156,6 +181,20 @@ impl Balances {
         })
     }
 
+    /// Sum of all the balances, i.e. amount of tokens held by the canister address
+    pub fn total() -> Nat {
+        STATE.with(|state| {
+            state
+                .borrow()
+                .balances
+                .0
+                .values()
+                .fold(Nat::from(0), |total, balance| {
+                    total + balance.amount.clone()
+                })
+        })
+    }
+
     pub fn contains(address: &Address) -> bool {
         STATE.with(|state| state.borrow().balances.0.contains_key(address))
     }
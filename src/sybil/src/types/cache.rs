@@ -11,15 +11,19 @@ use serde::{Deserialize, Serialize};
 use hex::FromHexError;
 use ic_web3_rs::signing::keccak256;
 use serde_json::Error as SerdeError;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
-use crate::utils::signature::{get_eth_v, sign};
+use crate::utils::signature::{
+    get_eth_v, sign, sign_schnorr, SchnorrAlgorithm, SchnorrKeyId, SignWithSchnorrArgument,
+};
 use crate::{
-    clone_with_state, log,
+    log,
     utils::{
         address::{self, AddressError},
-        canister::{self, CanisterError},
+        canister::CanisterError,
         nat,
+        retry::{Reject, Retryable},
         signature::SignatureError,
         time,
     },
@@ -27,6 +31,7 @@ use crate::{
 use crate::{HTTP_CACHE, SIGNATURES_CACHE};
 
 use super::rate_data::AssetDataResult;
+use super::signer::Signer;
 use super::{Seconds, Timestamp};
 
 const HTTP_WAITING_DELAY_SECS: u64 = 3;
@@ -113,14 +118,32 @@ pub struct HttpCacheStats {
 pub enum HttpCacheError {
     #[error("HTTP outcall error with message: {0}")]
     HttpOutcallError(String),
+    /// Rejected with `SysTransient`, e.g. the outcall has timed out or the connection has failed
+    #[error("HTTP outcall error with message: {0}")]
+    TransientHttpOutcallError(String),
+    /// Rejected with `SysTransient` because the replicas got different responses
+    #[error("HTTP outcall error with message: {0}")]
+    NoConsensus(String),
     #[error("Got error from server: {0}")]
     ServerError(String),
+    /// 4xx responses, e.g. an invalid api key of the owner
+    #[error("Got client error from server: {0}")]
+    ClientError(String),
     #[error("Invalid response body json: {0}")]
     InvalidResponseBodyJson(#[from] SerdeError),
     #[error("Invalid response body resolver: {0}")]
     InvalidResponseBodyResolver(String),
 }
 
+impl Retryable for HttpCacheError {
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            HttpCacheError::TransientHttpOutcallError(_) | HttpCacheError::NoConsensus(_)
+        )
+    }
+}
+
 impl HttpCache {
     pub async fn request_with_access(
         request: &CanisterHttpRequestArgument,
@@ -142,6 +165,26 @@ impl HttpCache {
         response
     }
 
+    /// Returns the cached response if it is still fresh, otherwise makes the outcall
+    /// without storing its response, so the previews don't fill the cache
+    pub async fn request_without_caching(
+        request: &CanisterHttpRequestArgument,
+    ) -> Result<(HttpResponse, Seconds), HttpCacheError> {
+        let cached = HTTP_CACHE.with(|c| {
+            c.borrow()
+                .entries
+                .get(&Self::cache_key(request))
+                .filter(|entry| !entry.is_expired())
+                .and_then(|entry| Some((entry.response.clone()?, entry.cached_at)))
+        });
+
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        Ok((Self::outcall(request).await?, time::in_seconds()))
+    }
+
     pub async fn request(
         &mut self,
         request: &CanisterHttpRequestArgument,
@@ -150,7 +193,7 @@ impl HttpCache {
         log!("[HTTP CACHE] got request");
         self.stats.total_requests += 1;
 
-        let Some(entry) = self.entries.get(&request.url) else {
+        let Some(entry) = self.entries.get(&Self::cache_key(request)) else {
             log!("[HTTP CACHE] record not found in cache");
             return self.force_request(request, expr_freq).await;
         };
@@ -182,51 +225,86 @@ impl HttpCache {
         request: &CanisterHttpRequestArgument,
         expr_freq: Seconds,
     ) -> Result<(HttpResponse, Seconds), HttpCacheError> {
-        let mut cycles =
-            HTTP_OUTCALL_REQUEST_CYCLES + (MAX_RESPONSE_BYTES * HTTP_OUTCALL_PAYLOAD_CYCLES);
-        if let Some(body) = &request.body {
-            cycles += body.len() as u128 * HTTP_OUTCALL_PAYLOAD_CYCLES;
-        }
-
+        let cache_key = Self::cache_key(request);
         self.entries
-            .insert(request.url.clone(), HttpCacheEntry::default());
+            .insert(cache_key.clone(), HttpCacheEntry::default());
 
-        let response = http_request(request.clone(), cycles)
+        let response = Self::outcall(request)
             .await
-            .map_err(|(_, msg)| {
-                self.stats.misses += 1;
-                HttpCacheError::HttpOutcallError(msg)
-            })?
-            .0;
-
-        if nat::to_u64(&response.status) >= 400 {
-            let msg =
-                String::from_utf8(response.body).unwrap_or_else(|_| "unknown error".to_string());
+            .inspect_err(|_| self.stats.misses += 1)?;
 
-            self.stats.misses += 1;
-
-            return Err(HttpCacheError::ServerError(msg));
-        }
-
-        let entry = self.entries.get_mut(&request.url);
+        let entry = self.entries.get_mut(&cache_key);
         if let Some(entry) = entry {
             entry.response = Some(response.clone());
             entry.cached_at = time::in_seconds();
             entry.expr_freq = expr_freq;
         } else {
             self.entries.insert(
-                request.url.clone(),
+                cache_key.clone(),
                 HttpCacheEntry::new(response.clone(), expr_freq),
             );
         }
 
         self.stats.hits += 1;
 
-        let entry = self.entries.get(&request.url).expect("entry not found");
+        let entry = self.entries.get(&cache_key).expect("entry not found");
 
         Ok((response, entry.cached_at))
     }
 
+    async fn outcall(
+        request: &CanisterHttpRequestArgument,
+    ) -> Result<HttpResponse, HttpCacheError> {
+        let mut cycles =
+            HTTP_OUTCALL_REQUEST_CYCLES + (MAX_RESPONSE_BYTES * HTTP_OUTCALL_PAYLOAD_CYCLES);
+        if let Some(body) = &request.body {
+            cycles += body.len() as u128 * HTTP_OUTCALL_PAYLOAD_CYCLES;
+        }
+
+        let response = http_request(request.clone(), cycles)
+            .await
+            .map_err(|(code, msg)| {
+                let reject = Reject::new(code, msg);
+                if reject.is_no_consensus() {
+                    HttpCacheError::NoConsensus(reject.msg)
+                } else if reject.is_transient() {
+                    HttpCacheError::TransientHttpOutcallError(reject.msg)
+                } else {
+                    HttpCacheError::HttpOutcallError(reject.msg)
+                }
+            })?
+            .0;
+
+        let status = nat::to_u64(&response.status);
+        if status >= 400 {
+            let msg =
+                String::from_utf8(response.body).unwrap_or_else(|_| "unknown error".to_string());
+
+            return Err(if status < 500 {
+                HttpCacheError::ClientError(msg)
+            } else {
+                HttpCacheError::ServerError(msg)
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Requests are identified by the hash of the url, method, headers and body,
+    /// so different requests to the same url are cached separately
+    /// and the api keys they contain are not kept in plain text
+    fn cache_key(request: &CanisterHttpRequestArgument) -> String {
+        let mut data = request.url.as_bytes().to_vec();
+        data.extend(format!("\n{:?}", request.method).as_bytes());
+        for header in &request.headers {
+            data.extend(format!("\n{}:{}", header.name, header.value).as_bytes());
+        }
+        data.push(b'\n');
+        data.extend(request.body.clone().unwrap_or_default());
+
+        hex::encode(keccak256(&data))
+    }
+
     pub fn clean(&mut self) {
         if self.entries.len() <= self.capacity {
             return;
@@ -261,6 +339,8 @@ pub enum SignaturesCacheError {
     InvalidSignature(#[from] FromHexError),
     #[error("Unable to sign with ecdsa: {0}")]
     SignWithECDSAError(String),
+    #[error("Unable to sign with schnorr: {0}")]
+    SignWithSchnorrError(String),
     #[error("Canister error: {0}")]
     CanisterError(#[from] CanisterError),
     #[error("Address error: {0}")]
@@ -278,27 +358,88 @@ pub struct SignaturesCache {
 }
 
 impl SignaturesCache {
-    pub async fn eth_sign_with_access(data: &[u8]) -> Result<Vec<u8>, SignaturesCacheError> {
+    pub async fn sign_with_access(
+        data: &[u8],
+        signer: &Signer,
+    ) -> Result<Vec<u8>, SignaturesCacheError> {
         let mut cache = SIGNATURES_CACHE.with(|c| c.borrow().clone());
-        let signature = cache.eth_sign(data).await;
+        let signature = cache.sign(data, signer).await;
         SIGNATURES_CACHE.with(|c| c.replace(cache));
         signature
     }
 
-    pub async fn eth_sign(&mut self, data: &[u8]) -> Result<Vec<u8>, SignaturesCacheError> {
+    pub async fn sign(
+        &mut self,
+        data: &[u8],
+        signer: &Signer,
+    ) -> Result<Vec<u8>, SignaturesCacheError> {
+        match signer.scheme.schnorr_algorithm() {
+            Some(algorithm) => self.schnorr_sign(data, signer, algorithm).await,
+            None => self.eth_sign(data, signer).await,
+        }
+    }
+
+    pub async fn schnorr_sign(
+        &mut self,
+        data: &[u8],
+        signer: &Signer,
+        algorithm: SchnorrAlgorithm,
+    ) -> Result<Vec<u8>, SignaturesCacheError> {
+        // BIP340 signs the sha256 hash, Ed25519 hashes the message by itself
+        let message = match algorithm {
+            SchnorrAlgorithm::Bip340Secp256k1 => Sha256::digest(data).to_vec(),
+            SchnorrAlgorithm::Ed25519 => data.to_vec(),
+        };
+
+        let cache_key = format!(
+            "{}:{}",
+            signer.cache_key(),
+            hex::encode(keccak256(&message))
+        );
+        if let Some(signature) = self.signatures.get(&cache_key) {
+            log!("[SIGNATURE CACHE] signature found in cache");
+            return Ok(hex::decode(signature)?);
+        }
+
+        let call_args = SignWithSchnorrArgument {
+            message,
+            derivation_path: signer.derivation_path.clone(),
+            key_id: SchnorrKeyId {
+                algorithm,
+                name: signer.key_name.clone(),
+            },
+        };
+
+        let signature = sign_schnorr(call_args)
+            .await
+            .map_err(|(_, msg)| SignaturesCacheError::SignWithSchnorrError(msg))?
+            .0
+            .signature;
+
+        self.signatures.insert(cache_key, hex::encode(&signature));
+
+        log!("[SIGNATURE CACHE] signature was not found in cache");
+        Ok(signature)
+    }
+
+    pub async fn eth_sign(
+        &mut self,
+        data: &[u8],
+        signer: &Signer,
+    ) -> Result<Vec<u8>, SignaturesCacheError> {
         let sign_data = keccak256(data).to_vec();
-        if let Some(signature) = self.signatures.get(&hex::encode(&sign_data)) {
+        let cache_key = format!("{}:{}", signer.cache_key(), hex::encode(&sign_data));
+        if let Some(signature) = self.signatures.get(&cache_key) {
             log!("[SIGNATURE CACHE] signature found in cache");
             return Ok(hex::decode(signature)?);
         }
 
-        let key_name = clone_with_state!(key_name);
         let call_args = SignWithEcdsaArgument {
             message_hash: sign_data.clone(),
-            derivation_path: vec![ic_cdk::id().as_slice().to_vec()],
+            derivation_path: signer.derivation_path.clone(),
             key_id: EcdsaKeyId {
                 curve: EcdsaCurve::Secp256k1,
-                name: key_name,
+                name: signer.key_name.clone(),
             },
         };
 
@@ -308,7 +449,7 @@ impl SignaturesCache {
             .0
             .signature;
 
-        let pub_key = canister::eth_address().await?;
+        let pub_key = signer.eth_address().await?;
 
         signature.push(get_eth_v(
             &signature,
@@ -316,8 +457,7 @@ impl SignaturesCache {
             &address::to_h160(&pub_key)?,
         )?);
 
-        self.signatures
-            .insert(hex::encode(&sign_data), hex::encode(&signature));
+        self.signatures.insert(cache_key, hex::encode(&signature));
 
         log!("[SIGNATURE CACHE] signature was not found in cache");
         Ok(signature)
@@ -0,0 +1,289 @@
+use candid::CandidType;
+use ic_certified_map::{
+    fork, fork_hash, labeled, labeled_hash, AsHashTree, Hash, HashTree, RbTree,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::{
+    feeds::{Feed, FeedStorage},
+    rate_data::AssetDataResult,
+};
+use crate::CERTIFIED_FEEDS;
+
+const FEEDS_LABEL: &[u8] = b"feeds";
+const HTTP_ASSETS_LABEL: &[u8] = b"http_assets";
+/// Path of the certified http response of a feed, followed by the feed id
+pub const CERTIFIED_HTTP_PATH: &str = "/get_latest_certified/";
+
+#[derive(Error, Debug)]
+pub enum CertifiedDataError {
+    #[error("Feed not found")]
+    FeedNotFound,
+    #[error("Feed has no data yet")]
+    NoData,
+    #[error("Certificate is available only in query calls")]
+    CertificateUnavailable,
+    #[error("Unable to serialize witness: {0}")]
+    Serialization(String),
+}
+
+/// Latest feed value together with the IC certificate and the witness proving it.
+/// The witness is a CBOR encoded hash tree with the `feeds/<id>` leaf,
+/// which contains the sha256 hash of the packed asset data.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CertifiedAssetData {
+    pub data: AssetDataResult,
+    pub certificate: Vec<u8>,
+    pub witness: Vec<u8>,
+}
+
+/// Latest feed value as the http response body, the witness contains the
+/// `http_assets/<path>` leaf with the sha256 hash of the body, as the http gateways expect
+pub struct CertifiedHttpResponse {
+    pub body: Vec<u8>,
+    pub certificate: Vec<u8>,
+    pub witness: Vec<u8>,
+}
+
+/// Hash trees of the latest values of the feeds, the `feeds` tree certifies the packed data
+/// returned in the candid calls and the `http_assets` tree the http response bodies.
+/// The root hash of both is set as the canister certified data
+#[derive(Default)]
+pub struct CertifiedFeeds {
+    feeds: RbTree<String, Hash>,
+    http_assets: RbTree<String, Hash>,
+}
+
+impl CertifiedFeeds {
+    pub fn insert(id: &str, data: &AssetDataResult) {
+        CERTIFIED_FEEDS.with(|certified_feeds| {
+            let mut certified_feeds = certified_feeds.borrow_mut();
+            certified_feeds.insert_data(id, data);
+            certified_feeds.certify();
+        })
+    }
+
+    pub fn remove(id: &str) {
+        CERTIFIED_FEEDS.with(|certified_feeds| {
+            let mut certified_feeds = certified_feeds.borrow_mut();
+            certified_feeds.feeds.delete(id.as_bytes());
+            certified_feeds.http_assets.delete(http_path(id).as_bytes());
+            certified_feeds.certify();
+        })
+    }
+
+    /// Builds the trees from scratch using the data stored in the feeds
+    pub fn rebuild() {
+        let certified_feeds = Self::from_feeds(FeedStorage::get_all(None));
+
+        certified_feeds.certify();
+        CERTIFIED_FEEDS.with(|c| c.replace(certified_feeds));
+    }
+
+    pub fn get(id: &str) -> Result<CertifiedAssetData, CertifiedDataError> {
+        let data = Self::data(id)?;
+
+        let certificate =
+            ic_cdk::api::data_certificate().ok_or(CertifiedDataError::CertificateUnavailable)?;
+
+        let witness = CERTIFIED_FEEDS
+            .with(|certified_feeds| serialize_witness(&certified_feeds.borrow().witness(id)))?;
+
+        Ok(CertifiedAssetData {
+            data,
+            certificate,
+            witness,
+        })
+    }
+
+    pub fn get_http(id: &str) -> Result<CertifiedHttpResponse, CertifiedDataError> {
+        let body = http_body(&Self::data(id)?);
+
+        let certificate =
+            ic_cdk::api::data_certificate().ok_or(CertifiedDataError::CertificateUnavailable)?;
+
+        let witness = CERTIFIED_FEEDS.with(|certified_feeds| {
+            serialize_witness(&certified_feeds.borrow().http_witness(&http_path(id)))
+        })?;
+
+        Ok(CertifiedHttpResponse {
+            body,
+            certificate,
+            witness,
+        })
+    }
+
+    fn data(id: &str) -> Result<AssetDataResult, CertifiedDataError> {
+        FeedStorage::get(id)
+            .ok_or(CertifiedDataError::FeedNotFound)?
+            .data
+            .ok_or(CertifiedDataError::NoData)
+    }
+
+    fn from_feeds(feeds: impl IntoIterator<Item = Feed>) -> Self {
+        let mut certified_feeds = CertifiedFeeds::default();
+        for feed in feeds {
+            if let Some(data) = &feed.data {
+                certified_feeds.insert_data(&feed.id, data);
+            }
+        }
+
+        certified_feeds
+    }
+
+    fn insert_data(&mut self, id: &str, data: &AssetDataResult) {
+        self.feeds.insert(id.to_string(), data.certified_hash());
+        self.http_assets
+            .insert(http_path(id), Sha256::digest(http_body(data)).into());
+    }
+
+    fn witness(&self, id: &str) -> HashTree<'_> {
+        fork(
+            labeled(FEEDS_LABEL, self.feeds.witness(id.as_bytes())),
+            HashTree::Pruned(labeled_hash(
+                HTTP_ASSETS_LABEL,
+                &self.http_assets.root_hash(),
+            )),
+        )
+    }
+
+    fn http_witness(&self, path: &str) -> HashTree<'_> {
+        fork(
+            HashTree::Pruned(labeled_hash(FEEDS_LABEL, &self.feeds.root_hash())),
+            labeled(HTTP_ASSETS_LABEL, self.http_assets.witness(path.as_bytes())),
+        )
+    }
+
+    fn root_hash(&self) -> Hash {
+        fork_hash(
+            &labeled_hash(FEEDS_LABEL, &self.feeds.root_hash()),
+            &labeled_hash(HTTP_ASSETS_LABEL, &self.http_assets.root_hash()),
+        )
+    }
+
+    fn certify(&self) {
+        ic_cdk::api::set_certified_data(&self.root_hash());
+    }
+}
+
+/// Body of the certified http response, its hash is kept in the `http_assets` tree
+pub fn http_body(data: &AssetDataResult) -> Vec<u8> {
+    serde_json::to_vec(data).expect("asset data should be serializable")
+}
+
+fn http_path(id: &str) -> String {
+    format!("{CERTIFIED_HTTP_PATH}{id}")
+}
+
+fn serialize_witness(witness: &HashTree) -> Result<Vec<u8>, CertifiedDataError> {
+    let mut serializer = serde_cbor::ser::Serializer::new(vec![]);
+    serializer
+        .self_describe()
+        .map_err(|err| CertifiedDataError::Serialization(err.to_string()))?;
+    witness
+        .serialize(&mut serializer)
+        .map_err(|err| CertifiedDataError::Serialization(err.to_string()))?;
+
+    Ok(serializer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::rate_data::AssetData;
+
+    fn feed(id: &str, value: u64) -> Feed {
+        Feed {
+            id: id.to_string(),
+            data: Some(AssetDataResult {
+                data: AssetData::CustomNumber {
+                    id: id.to_string(),
+                    value,
+                    decimals: 0,
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut certified_feeds = CertifiedFeeds::default();
+        let empty_root = certified_feeds.root_hash();
+
+        let eth = feed("eth", 1);
+        let data = eth.data.as_ref().unwrap();
+        certified_feeds.insert_data(&eth.id, data);
+        assert_ne!(certified_feeds.root_hash(), empty_root);
+        assert_eq!(
+            certified_feeds.feeds.get(b"eth"),
+            Some(&data.certified_hash())
+        );
+
+        // the witness proves the leaf against the certified root
+        assert_eq!(
+            certified_feeds.witness("eth").reconstruct(),
+            certified_feeds.root_hash()
+        );
+
+        certified_feeds.feeds.delete(b"eth");
+        certified_feeds
+            .http_assets
+            .delete(http_path("eth").as_bytes());
+        assert_eq!(certified_feeds.root_hash(), empty_root);
+    }
+
+    #[test]
+    fn test_http_assets() {
+        let certified_feeds = CertifiedFeeds::from_feeds(vec![feed("eth", 1), feed("btc", 2)]);
+
+        // the gateways check the hash of the exact response body under the request path
+        let body = http_body(feed("eth", 1).data.as_ref().unwrap());
+        let body_hash: Hash = Sha256::digest(&body).into();
+        assert_eq!(
+            certified_feeds
+                .http_assets
+                .get(b"/get_latest_certified/eth"),
+            Some(&body_hash)
+        );
+
+        let witness = certified_feeds.http_witness("/get_latest_certified/eth");
+        assert_eq!(witness.reconstruct(), certified_feeds.root_hash());
+        assert_eq!(
+            witness.reconstruct(),
+            certified_feeds.witness("btc").reconstruct()
+        );
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let feeds = vec![
+            feed("eth", 1),
+            feed("btc", 2),
+            Feed {
+                id: "no_data".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let mut certified_feeds = CertifiedFeeds::default();
+        for feed in &feeds {
+            if let Some(data) = &feed.data {
+                certified_feeds.insert_data(&feed.id, data);
+            }
+        }
+
+        // the root hash doesn't depend on the insertion order
+        let rebuilt = CertifiedFeeds::from_feeds(feeds.into_iter().rev());
+        assert_eq!(rebuilt.root_hash(), certified_feeds.root_hash());
+        assert!(rebuilt.feeds.get(b"no_data").is_none());
+
+        // a new value changes the root hash
+        let updated = CertifiedFeeds::from_feeds(vec![feed("eth", 3), feed("btc", 2)]);
+        assert_ne!(updated.root_hash(), certified_feeds.root_hash());
+        assert_eq!(updated.witness("btc").reconstruct(), updated.root_hash());
+    }
+}
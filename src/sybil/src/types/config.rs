@@ -2,7 +2,7 @@ use candid::CandidType;
 use candid::Principal;
 use serde::{Deserialize, Serialize};
 
-use super::balances::BalancesCfg;
+use super::{balances::BalancesCfg, rpc::RpcTransport};
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct Cfg {
@@ -10,9 +10,22 @@ pub struct Cfg {
     pub fallback_xrc: Principal,
     pub evm_rpc_canister: Principal,
     pub rpc_wrapper: String,
+    /// Wrappers used in order when the main one is unhealthy
+    pub rpc_wrapper_fallbacks: Option<Vec<String>>,
+    /// `cacheTTL` parameter of the wrappers in milliseconds, 30 seconds by default
+    pub rpc_wrapper_cache_ttl: Option<u64>,
     pub mock: bool,
     pub key_name: String,
     pub balances_cfg: BalancesCfg,
+    /// Canisters the canister call sources are allowed to call, none by default
+    pub canister_call_canisters: Option<Vec<Principal>>,
+    /// Read-only methods the canister call sources are allowed to call,
+    /// the ICRC-1 queries by default
+    pub canister_call_methods: Option<Vec<String>>,
+    /// Canister serving the Bitcoin API, the management canister by default
+    pub bitcoin_canister: Option<Principal>,
+    /// Default transport of the EVM RPC calls, HTTPS outcalls by default
+    pub rpc_transport: Option<RpcTransport>,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -21,7 +34,13 @@ pub struct UpdateCfg {
     pub fallback_xrc: Option<Principal>,
     pub evm_rpc_canister: Option<Principal>,
     pub rpc_wrapper: Option<String>,
+    pub rpc_wrapper_fallbacks: Option<Vec<String>>,
+    pub rpc_wrapper_cache_ttl: Option<u64>,
     pub mock: Option<bool>,
     pub key_name: Option<String>,
     pub balances_cfg: Option<BalancesCfg>,
+    pub canister_call_canisters: Option<Vec<Principal>>,
+    pub canister_call_methods: Option<Vec<String>>,
+    pub bitcoin_canister: Option<Principal>,
+    pub rpc_transport: Option<RpcTransport>,
 }
@@ -1,6 +1,6 @@
-use std::{collections::HashMap, time::Duration};
+use std::collections::HashMap;
 
-use candid::CandidType;
+use candid::{CandidType, Nat};
 use ic_web3_rs::futures::future::join_all;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -8,9 +8,12 @@ use thiserror::Error;
 
 use super::{
     balances::{BalanceError, Balances},
+    certified_data::CertifiedFeeds,
     exchange_rate::{Asset, AssetClass, ExchangeRate, ExchangeRateError, GetExchangeRateRequest},
     rate_data::{AssetData, AssetDataResult, RateDataError},
+    signer::{SignatureScheme, Signer, SignerScope},
     source::{HttpSource, Source, SourceError},
+    source_health::SourcesHealth,
     state, Address, Seconds, Timestamp,
 };
 use crate::{
@@ -18,13 +21,23 @@ use crate::{
     methods::{custom_feeds::CreateCustomFeedRequest, default_feeds::CreateDefaultFeedRequest},
     metrics,
     types::exchange_rate::Service,
-    utils::{canister, nat, parsed_number::ParsedNumber, siwe::SiweError, sleep, time, vec},
+    utils::{
+        canister, nat,
+        parsed_number::ParsedNumber,
+        retry::{retry, RetryPolicy, Retryable},
+        siwe::SiweError,
+        time, vec,
+    },
     CACHE, STATE,
 };
 
-const RATE_FETCH_DEFAULT_XRC_MAX_RETRIES: u64 = 5;
-const RATE_FETCH_FALLBACK_XRC_MAX_RETRIES: u64 = 5;
-const WAITING_BEFORE_RETRY_MS: Duration = Duration::from_millis(500);
+const XRC_RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_retries: Some(4),
+    initial_delay_ms: Some(500),
+    max_delay_ms: Some(4000),
+    multiplier: None,
+    timeout_ms: None,
+};
 
 #[derive(Error, Debug)]
 pub enum FeedError {
@@ -52,12 +65,31 @@ pub enum FeedError {
     Canister(#[from] canister::CanisterError),
     #[error("Error in sources: {0:?}")]
     SourceError(Vec<SourceError>),
+    #[error("Feed has no data yet")]
+    NoData,
+    #[error("Feed data is too old: age {age}s, max age {max_age}s")]
+    DataIsTooOld { age: Seconds, max_age: Seconds },
+    #[error("All the sources are quarantined")]
+    AllSourcesQuarantined,
+}
+
+impl Retryable for FeedError {
+    /// The exchange rate canister errors are retried, except the rate limit
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            FeedError::ExchangeRateCanisterError(err)
+                if !matches!(err, ExchangeRateError::RateLimited)
+        )
+    }
 }
 
 pub struct RateResult {
     pub rate: Value,
     pub cached_at: Seconds,
     pub bytes: usize,
+    /// Beginning of the raw response, for the HTTP sources only
+    pub excerpt: Option<String>,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
@@ -102,6 +134,23 @@ pub struct FeedStatus {
     pub(crate) last_update: Timestamp,
     pub(crate) updated_counter: u64,
     pub(crate) requests_counter: u64,
+    /// Time of the stored data, which can be older than the update when it's served from the caches
+    pub(crate) data_timestamp: Option<Timestamp>,
+    /// Quarantined sources skipped on the last update, the data is aggregated from the rest
+    pub(crate) skipped_sources: Option<u64>,
+}
+
+/// Last stored value of a feed, served without fetching the sources
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct LatestAssetData {
+    pub data: AssetDataResult,
+    /// Time of the data, see `FeedStatus.data_timestamp`
+    pub updated_at: Timestamp,
+    pub age: Seconds,
+    /// Whether the value is older than the feed update frequency
+    pub is_stale: bool,
+    /// Quarantined sources the value isn't aggregated from, see `SourcesHealth`
+    pub skipped_sources: u64,
 }
 
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
@@ -116,6 +165,8 @@ pub struct Feed {
     pub status: FeedStatus,
     pub owner: Address,
     pub data: Option<AssetDataResult>,
+    pub signer_scope: Option<SignerScope>,
+    pub signature_scheme: Option<SignatureScheme>,
 }
 
 impl Feed {
@@ -157,6 +208,8 @@ impl From<CreateCustomFeedRequest> for Feed {
             new_sources: Some(req.sources),
             update_freq: nat::to_u64(&req.update_freq),
             decimals: req.decimals,
+            signer_scope: req.signer_scope,
+            signature_scheme: req.signature_scheme,
             ..Default::default()
         }
     }
@@ -184,30 +237,40 @@ impl FeedStorage {
     pub fn remove(id: &str) {
         STATE.with(|state| {
             state.borrow_mut().feeds.0.remove(id);
-        })
+        });
+
+        CertifiedFeeds::remove(id);
     }
 
     pub async fn rate(id: &str, with_signature: bool) -> Result<AssetDataResult, FeedError> {
-        let mut rate = match Self::get(id) {
-            Some(feed) => match feed.feed_type.clone() {
-                FeedType::Default => {
-                    log!("[FEEDS] default feed requested: feed ID: {}", id);
-                    Self::get_default_rate(&feed).await
-                }
-                FeedType::Custom | FeedType::CustomNumber | FeedType::CustomString => {
-                    log!(
-                        "[FEEDS] cusom feed requested: feed ID: {}, sources: {:#?}",
-                        id,
-                        feed.new_sources.clone().unwrap()
-                    );
-                    Self::get_custom_rate(&feed, &feed.new_sources.clone().unwrap()).await
-                }
-            },
-            None => Err(FeedError::FeedNotFound),
+        let feed = Self::get(id).ok_or(FeedError::FeedNotFound)?;
+
+        STATE.with(|state| {
+            if let Some(feed) = state.borrow_mut().feeds.0.get_mut(id) {
+                feed.status.requests_counter += 1;
+            }
+        });
+
+        let (mut rate, data_timestamp, skipped_sources) = match feed.feed_type.clone() {
+            FeedType::Default => {
+                log!("[FEEDS] default feed requested: feed ID: {}", id);
+                Self::get_default_rate(&feed).await.map(|rate| {
+                    let timestamp = rate.data.timestamp().unwrap_or_else(time::in_seconds);
+                    (rate, timestamp, 0)
+                })
+            }
+            FeedType::Custom | FeedType::CustomNumber | FeedType::CustomString => {
+                log!(
+                    "[FEEDS] cusom feed requested: feed ID: {}, sources: {:#?}",
+                    id,
+                    feed.new_sources.clone().unwrap()
+                );
+                Self::get_custom_rate(&feed, &feed.new_sources.clone().unwrap()).await
+            }
         }?;
 
         if with_signature {
-            rate.sign().await?;
+            rate.sign(&Signer::for_feed(&feed)).await?;
         }
 
         STATE.with(|state| {
@@ -215,10 +278,16 @@ impl FeedStorage {
             let feed = state.feeds.0.get_mut(id).ok_or(FeedError::FeedNotFound)?;
 
             feed.data = Some(rate.clone());
+            feed.status.last_update = time::in_seconds();
+            feed.status.data_timestamp = Some(data_timestamp);
+            feed.status.skipped_sources = Some(skipped_sources);
+            feed.status.updated_counter += 1;
 
             Result::<(), FeedError>::Ok(())
         })?;
 
+        CertifiedFeeds::insert(id, &rate);
+
         log!("[FEEDS] requested rate: {:#?}", rate);
 
         Ok(rate)
@@ -241,13 +310,7 @@ impl FeedStorage {
         let xrc = Service(clone_with_state!(exchange_rate_canister));
         metrics!(inc XRC_CALLS);
 
-        let exchange_rate = match Self::call_xrc_with_attempts(
-            xrc,
-            req.clone(),
-            RATE_FETCH_DEFAULT_XRC_MAX_RETRIES,
-        )
-        .await
-        {
+        let exchange_rate = match Self::call_xrc_with_retries(xrc, req.clone()).await {
             Ok(exchange_rate) => {
                 metrics!(inc SUCCESSFUL_XRC_CALLS);
                 exchange_rate
@@ -261,12 +324,7 @@ impl FeedStorage {
                 let xrc = Service(clone_with_state!(fallback_xrc));
 
                 metrics!(inc FALLBACK_XRC_CALLS);
-                let result = Self::call_xrc_with_attempts(
-                    xrc,
-                    req.clone(),
-                    RATE_FETCH_FALLBACK_XRC_MAX_RETRIES,
-                )
-                .await?;
+                let result = Self::call_xrc_with_retries(xrc, req.clone()).await?;
                 metrics!(inc SUCCESSFUL_FALLBACK_XRC_CALLS);
                 result
             }
@@ -291,13 +349,15 @@ impl FeedStorage {
         Ok(rate_data)
     }
 
-    async fn call_xrc_with_attempts(
+    async fn call_xrc_with_retries(
         exchange_rate_canister: Service,
-        mut req: GetExchangeRateRequest,
-        max_attempts: u64,
+        req: GetExchangeRateRequest,
     ) -> Result<ExchangeRate, FeedError> {
-        let mut exchange_rate = ExchangeRate::default();
-        for attempt in 0..(max_attempts) {
+        let exchange_rate_canister = &exchange_rate_canister;
+        let mut attempt = 0;
+
+        retry(&XRC_RETRY_POLICY, || {
+            let mut req = req.clone();
             req.timestamp = Some(time::in_seconds() - 5);
 
             log!(
@@ -305,70 +365,47 @@ impl FeedStorage {
                 attempt,
                 req
             );
+            attempt += 1;
 
-            let exchange_rate_result = Result::<_, _>::from(
-                exchange_rate_canister
-                    .get_exchange_rate(req.clone())
-                    .await
-                    .map_err(|(_, msg)| FeedError::UnableToGetRate(msg))?
-                    .0,
-            );
-
-            match exchange_rate_result {
-                Ok(_exchange_rate) => {
-                    log!(
-                        "[FEEDS] get_default_rate got response from xrc: {:?}",
-                        _exchange_rate
-                    );
-                    exchange_rate = _exchange_rate;
-                    break;
-                }
-                Err(ExchangeRateError::RateLimited) => {
-                    return Err(FeedError::ExchangeRateCanisterError(
-                        ExchangeRateError::RateLimited,
-                    ));
-                }
-                Err(err) => {
-                    log!(
-                        "[FEEDS] Exchange rate Error on attempt {}: {}",
-                        attempt,
-                        err,
-                    );
-
-                    sleep(WAITING_BEFORE_RETRY_MS).await;
-
-                    if attempt == max_attempts - 1 {
-                        return Err(FeedError::ExchangeRateCanisterError(err));
-                    }
-
-                    continue;
-                }
-            };
-        }
+            async move {
+                let exchange_rate = Result::<_, _>::from(
+                    exchange_rate_canister
+                        .get_exchange_rate(req)
+                        .await
+                        .map_err(|(_, msg)| FeedError::UnableToGetRate(msg))?
+                        .0,
+                )?;
 
-        return Ok(exchange_rate);
+                log!(
+                    "[FEEDS] get_default_rate got response from xrc: {:?}",
+                    exchange_rate
+                );
+                Ok(exchange_rate)
+            }
+        })
+        .await
     }
 
+    /// Returns the aggregated rate along with the time of the latest source response
+    /// and the number of the skipped quarantined sources
     pub async fn get_custom_rate(
         feed: &Feed,
         sources: &[Source],
-    ) -> Result<AssetDataResult, FeedError> {
-        let canister_addr = canister::eth_address().await?;
-
-        let futures = sources
-            .iter()
-            .map(|source| source.rate(feed.update_freq))
-            .collect::<Vec<_>>();
-
+    ) -> Result<(AssetDataResult, Timestamp, u64), FeedError> {
         let mut source_errs = Vec::new();
+        let mut skipped_sources = 0;
 
-        let results = join_all(futures)
+        let results = Self::fetch_rates(feed, sources, true)
             .await
             .into_iter()
             .filter_map(|res| match res {
                 Ok(res) => {
                     return Some(res);
                 }
+                Err(SourceError::Quarantined) => {
+                    skipped_sources += 1;
+                    None
+                }
                 Err(err) => {
                     log!("[FEEDS] error while getting custom rate: {:?}", err);
                     source_errs.push(err);
@@ -381,22 +418,112 @@ impl FeedStorage {
             return Err(FeedError::SourceError(source_errs));
         }
 
-        let bytes = results.iter().map(|res| res.bytes).sum::<usize>();
-        let fee_per_byte = state::get_cfg().balances_cfg.fee_per_byte;
-        let fee = fee_per_byte * bytes;
+        if results.is_empty() {
+            return Err(FeedError::AllSourcesQuarantined);
+        }
 
-        if !Balances::is_sufficient(&feed.owner, &fee)? {
+        Self::charge(&feed.owner, &results).await?;
+
+        let timestamp = results
+            .iter()
+            .map(|result| result.cached_at)
+            .max()
+            .unwrap_or_else(time::in_seconds);
+
+        if skipped_sources > 0 {
+            log!(
+                "[FEEDS] {} of {} sources are quarantined, aggregating the rest. feed: {}",
+                skipped_sources,
+                sources.len(),
+                feed.id
+            );
+        }
+
+        Ok((Self::aggregate(feed, &results)?, timestamp, skipped_sources))
+    }
+
+    /// Fetches the sources with the owner secrets, the results are in the order of the sources.
+    /// Unless `persist` is set, the indexed sources and the sources health are left untouched,
+    /// and the quarantined sources are fetched as well, otherwise they fail with `Quarantined`
+    pub async fn fetch_rates(
+        feed: &Feed,
+        sources: &[Source],
+        persist: bool,
+    ) -> Vec<Result<RateResult, SourceError>> {
+        let futures = sources.iter().map(|source| async move {
+            if !persist {
+                return Self::fetch_rate(feed, source, false).await;
+            }
+
+            let key = SourcesHealth::key(&feed.owner, source);
+            if SourcesHealth::is_quarantined(&key) {
+                log!("[FEEDS] source is quarantined, skipping. feed: {}", feed.id);
+                return Err(SourceError::Quarantined);
+            }
+
+            let started_at = time::in_millis();
+            let result = Self::fetch_rate(feed, source, true).await;
+            SourcesHealth::record(
+                &key,
+                source,
+                time::in_millis().saturating_sub(started_at),
+                &result,
+            );
+
+            result
+        });
+
+        join_all(futures).await
+    }
+
+    async fn fetch_rate(
+        feed: &Feed,
+        source: &Source,
+        persist: bool,
+    ) -> Result<RateResult, SourceError> {
+        let source = source.with_secrets(&feed.owner).await?;
+
+        if persist {
+            source.rate(feed.update_freq).await
+        } else {
+            source.preview(feed.update_freq).await
+        }
+    }
+
+    /// Charges the owner the fee for the bytes fetched by the sources
+    pub async fn charge(owner: &Address, results: &[RateResult]) -> Result<Nat, FeedError> {
+        Self::charge_bytes(owner, results.iter().map(|res| res.bytes).sum()).await
+    }
+
+    /// Charges the owner the fee for the fetched bytes
+    pub async fn charge_bytes(owner: &Address, bytes: usize) -> Result<Nat, FeedError> {
+        let canister_addr = canister::eth_address().await?;
+        let fee = Self::fee(bytes);
+
+        if !Balances::is_sufficient(owner, &fee)? {
             return Err(BalanceError::InsufficientBalance)?;
         };
 
+        Balances::reduce_amount(owner, &fee)?;
+        Balances::add_amount(&canister_addr, &fee)?;
+
+        Ok(fee)
+    }
+
+    /// Fee for the bytes fetched by the sources
+    pub fn fee(bytes: usize) -> Nat {
+        let fee_per_byte = state::get_cfg().balances_cfg.fee_per_byte;
+
+        fee_per_byte * bytes
+    }
+
+    /// Aggregates the source rates into the feed value according to the feed type
+    pub fn aggregate(feed: &Feed, results: &[RateResult]) -> Result<AssetDataResult, FeedError> {
         let (results, cached_at_timestamps): (Vec<_>, Vec<_>) = results
-            .into_iter()
-            .map(|res| (res.rate, res.cached_at))
+            .iter()
+            .map(|res| (res.rate.clone(), res.cached_at))
             .unzip();
 
-        Balances::reduce_amount(&feed.owner, &fee)?;
-        Balances::add_amount(&canister_addr, &fee)?;
-
         match feed.feed_type {
             FeedType::CustomNumber => {
                 let parsed_results = match results.first().expect("rate is empty") {
@@ -503,6 +630,33 @@ impl FeedStorage {
         }
     }
 
+    /// Returns the last stored value of the feed without fetching it.
+    /// The age is counted from the time of the data, fails if it's older than `max_age`
+    pub fn latest(id: &str, max_age: Option<Seconds>) -> Result<LatestAssetData, FeedError> {
+        let feed = Self::get(id).ok_or(FeedError::FeedNotFound)?;
+        let data = feed.data.ok_or(FeedError::NoData)?;
+
+        let updated_at = feed
+            .status
+            .data_timestamp
+            .unwrap_or(feed.status.last_update);
+        let age = time::in_seconds().saturating_sub(updated_at);
+
+        if let Some(max_age) = max_age {
+            if age > max_age {
+                return Err(FeedError::DataIsTooOld { age, max_age });
+            }
+        }
+
+        Ok(LatestAssetData {
+            data,
+            updated_at,
+            age,
+            is_stale: age > feed.update_freq,
+            skipped_sources: feed.status.skipped_sources.unwrap_or_default(),
+        })
+    }
+
     pub fn get(id: &str) -> Option<Feed> {
         STATE.with(|state| state.borrow().feeds.0.get(id).cloned())
     }
@@ -580,6 +734,7 @@ impl FeedStorage {
 
     pub fn clear() {
         STATE.with(|state| state.borrow_mut().feeds.0.clear());
+        CertifiedFeeds::rebuild();
     }
 }
 
@@ -0,0 +1,221 @@
+use candid::{CandidType, Nat};
+use ic_web3_rs::ic::get_eth_addr;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    balances::{BalanceError, Balances},
+    Address, Seconds, Timestamp,
+};
+use crate::{
+    clone_with_state,
+    utils::{
+        address::{self, AddressError},
+        canister::{self, CanisterError},
+        time,
+    },
+    STATE,
+};
+
+/// Period after which an unfinished funds migration can be started again
+const MIGRATION_TIMEOUT: Seconds = 30 * 60;
+
+#[derive(Error, Debug)]
+pub enum KeyRotationError {
+    #[error("Key rotation is already in progress")]
+    RotationInProgress,
+    #[error("No key rotation in progress")]
+    NoRotationInProgress,
+    #[error("New key is the same as the active one")]
+    SameKey,
+    #[error("Grace period is not over yet, rotation can be completed after {0}")]
+    GracePeriodNotOver(Timestamp),
+    #[error("Funds migration is in progress")]
+    MigrationInProgress,
+    #[error("Funds migration is not started")]
+    MigrationNotStarted,
+    #[error("Unable to get eth address: {0}")]
+    UnableToGetEthAddress(String),
+    #[error("Address error: {0}")]
+    Address(#[from] AddressError),
+    #[error("Balance error: {0}")]
+    Balance(#[from] BalanceError),
+    #[error("Canister error: {0}")]
+    Canister(#[from] CanisterError),
+}
+
+/// Key announced to replace the active one, signs along with it during the grace period
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PendingKey {
+    pub key_name: String,
+    pub eth_address: Address,
+    pub announced_at: Timestamp,
+    pub grace_period: Seconds,
+    /// Set while the funds are migrated, the rotation can't be completed again or cancelled meanwhile
+    pub migrating_since: Option<Timestamp>,
+}
+
+impl PendingKey {
+    pub fn activation_time(&self) -> Timestamp {
+        self.announced_at + self.grace_period
+    }
+
+    /// A migration interrupted by a trap keeps the lock, it's released after `MIGRATION_TIMEOUT`
+    fn is_migrating(&self, now: Timestamp) -> bool {
+        self.migrating_since
+            .is_some_and(|since| now < since + MIGRATION_TIMEOUT)
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RetiredKey {
+    pub key_name: String,
+    pub eth_address: Address,
+    pub retired_at: Timestamp,
+    /// Native balance left on the retired address, only the retired key can spend it
+    pub native_balance_left: Option<Nat>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SignerAddresses {
+    pub key_name: String,
+    pub active: Option<Address>,
+    pub next: Option<PendingKey>,
+    pub previous: Option<RetiredKey>,
+}
+
+pub struct KeyRotation;
+
+impl KeyRotation {
+    pub async fn announce(
+        key_name: String,
+        grace_period: Seconds,
+    ) -> Result<PendingKey, KeyRotationError> {
+        if Self::pending().is_some() {
+            return Err(KeyRotationError::RotationInProgress);
+        }
+
+        if key_name == clone_with_state!(key_name) {
+            return Err(KeyRotationError::SameKey);
+        }
+
+        let raw_address = get_eth_addr(None, None, key_name.clone())
+            .await
+            .map_err(KeyRotationError::UnableToGetEthAddress)?;
+
+        let pending_key = PendingKey {
+            key_name,
+            eth_address: address::from_h160(&raw_address)?,
+            announced_at: time::in_seconds(),
+            grace_period,
+            migrating_since: None,
+        };
+
+        STATE.with(|state| state.borrow_mut().next_key = Some(pending_key.clone()));
+
+        Ok(pending_key)
+    }
+
+    /// Returns the pending key if the grace period is over
+    pub fn ready() -> Result<PendingKey, KeyRotationError> {
+        let pending_key = Self::pending().ok_or(KeyRotationError::NoRotationInProgress)?;
+
+        if pending_key.activation_time() > time::in_seconds() {
+            return Err(KeyRotationError::GracePeriodNotOver(
+                pending_key.activation_time(),
+            ));
+        }
+
+        Ok(pending_key)
+    }
+
+    /// Locks the rotation before the funds are migrated, see `PendingKey.migrating_since`
+    pub fn start_migration() -> Result<PendingKey, KeyRotationError> {
+        let pending_key = Self::ready()?;
+        let now = time::in_seconds();
+
+        if pending_key.is_migrating(now) {
+            return Err(KeyRotationError::MigrationInProgress);
+        }
+
+        STATE.with(|state| {
+            if let Some(next_key) = state.borrow_mut().next_key.as_mut() {
+                next_key.migrating_since = Some(now);
+            }
+        });
+
+        Ok(PendingKey {
+            migrating_since: Some(now),
+            ..pending_key
+        })
+    }
+
+    /// Releases the lock of the failed migration, the rotation can be completed again
+    pub fn abort_migration() {
+        STATE.with(|state| {
+            if let Some(next_key) = state.borrow_mut().next_key.as_mut() {
+                next_key.migrating_since = None;
+            }
+        });
+    }
+
+    /// Makes the pending key active and moves the fee balance to its address.
+    /// The funds held by the previous address on-chain should be migrated under `start_migration`.
+    pub async fn complete(native_balance_left: Nat) -> Result<Address, KeyRotationError> {
+        let pending_key = Self::ready()?;
+        if pending_key.migrating_since.is_none() {
+            return Err(KeyRotationError::MigrationNotStarted);
+        }
+
+        let old_address = canister::eth_address().await?;
+
+        let fees = Balances::get_amount(&old_address)?;
+        if !Balances::contains(&pending_key.eth_address) {
+            Balances::add(&pending_key.eth_address)?;
+        }
+        Balances::add_amount(&pending_key.eth_address, &fees)?;
+        Balances::remove(&old_address)?;
+
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+
+            state.previous_key = Some(RetiredKey {
+                key_name: state.key_name.clone(),
+                eth_address: old_address,
+                retired_at: time::in_seconds(),
+                native_balance_left: Some(native_balance_left),
+            });
+            state.key_name = pending_key.key_name.clone();
+            state.eth_address = Some(pending_key.eth_address.clone());
+            state.next_key = None;
+        });
+
+        Ok(pending_key.eth_address)
+    }
+
+    pub fn cancel() -> Result<(), KeyRotationError> {
+        let pending_key = Self::pending().ok_or(KeyRotationError::NoRotationInProgress)?;
+        if pending_key.is_migrating(time::in_seconds()) {
+            return Err(KeyRotationError::MigrationInProgress);
+        }
+
+        STATE.with(|state| state.borrow_mut().next_key = None);
+        Ok(())
+    }
+
+    pub fn pending() -> Option<PendingKey> {
+        clone_with_state!(next_key)
+    }
+
+    pub fn addresses() -> SignerAddresses {
+        STATE.with(|state| {
+            let state = state.borrow();
+            SignerAddresses {
+                key_name: state.key_name.clone(),
+                active: state.eth_address.clone(),
+                next: state.next_key.clone(),
+                previous: state.previous_key.clone(),
+            }
+        })
+    }
+}
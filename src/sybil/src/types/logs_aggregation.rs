@@ -0,0 +1,217 @@
+use candid::CandidType;
+use ic_web3_rs::ethabi::Token;
+use num_bigint::{BigInt, Sign};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::utils::abi;
+
+/// Number of decimals kept in the average value
+const AVERAGE_DECIMALS: u32 = 18;
+
+#[derive(Error, Debug)]
+pub enum LogsAggregationError {
+    #[error("Event log field is not a number")]
+    FieldIsNotANumber,
+    #[error("No logs matching the filter")]
+    NoLogs,
+}
+
+/// Aggregation over all the logs matching the filter
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum LogsAggregation {
+    /// Number of the matching logs, the field is not used
+    Count,
+    Sum,
+    Average,
+    Min,
+    Max,
+    /// Field of the most recent log
+    Last,
+}
+
+impl LogsAggregation {
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            LogsAggregation::Sum
+                | LogsAggregation::Average
+                | LogsAggregation::Min
+                | LogsAggregation::Max
+        )
+    }
+}
+
+/// Running state of the aggregation, logs should be pushed in the chain order
+#[derive(Clone, Debug, Default)]
+pub struct LogsAccumulator {
+    pub count: u64,
+    pub sum: BigInt,
+    pub min: Option<BigInt>,
+    pub max: Option<BigInt>,
+    pub last: Option<Value>,
+}
+
+impl LogsAccumulator {
+    /// Adds the field of the next log, the field can be omitted for `Count`
+    pub fn push(
+        &mut self,
+        aggregation: &LogsAggregation,
+        field: Option<Token>,
+    ) -> Result<(), LogsAggregationError> {
+        self.count += 1;
+
+        let Some(field) = field else {
+            return Ok(());
+        };
+
+        if aggregation.is_numeric() {
+            let value =
+                abi::token_to_bigint(&field).ok_or(LogsAggregationError::FieldIsNotANumber)?;
+
+            self.sum += &value;
+            if self.min.as_ref().is_none_or(|min| &value < min) {
+                self.min = Some(value.clone());
+            }
+            if self.max.as_ref().is_none_or(|max| &value > max) {
+                self.max = Some(value);
+            }
+        }
+
+        if *aggregation == LogsAggregation::Last {
+            self.last = Some(abi::token_to_value(field));
+        }
+
+        Ok(())
+    }
+
+    /// Integers are returned as decimal strings, like the single log values
+    pub fn result(&self, aggregation: &LogsAggregation) -> Result<Value, LogsAggregationError> {
+        match aggregation {
+            LogsAggregation::Count => Ok(Value::from(self.count)),
+            LogsAggregation::Sum => Ok(Value::String(self.sum.to_string())),
+            LogsAggregation::Average if self.count == 0 => Err(LogsAggregationError::NoLogs),
+            LogsAggregation::Average => Ok(Value::String(divide(&self.sum, self.count))),
+            LogsAggregation::Min => self
+                .min
+                .as_ref()
+                .map(|min| Value::String(min.to_string()))
+                .ok_or(LogsAggregationError::NoLogs),
+            LogsAggregation::Max => self
+                .max
+                .as_ref()
+                .map(|max| Value::String(max.to_string()))
+                .ok_or(LogsAggregationError::NoLogs),
+            LogsAggregation::Last => self.last.clone().ok_or(LogsAggregationError::NoLogs),
+        }
+    }
+}
+
+/// Divides the value and formats the result as a decimal string with up to `AVERAGE_DECIMALS` decimals
+fn divide(value: &BigInt, divisor: u64) -> String {
+    let scaled = value * BigInt::from(10u8).pow(AVERAGE_DECIMALS) / BigInt::from(divisor);
+
+    let digits = format!(
+        "{:0>width$}",
+        scaled.magnitude().to_string(),
+        width = AVERAGE_DECIMALS as usize + 1
+    );
+    let (integer, fraction) = digits.split_at(digits.len() - AVERAGE_DECIMALS as usize);
+    let fraction = fraction.trim_end_matches('0');
+
+    let sign = if scaled.sign() == Sign::Minus {
+        "-"
+    } else {
+        ""
+    };
+    if fraction.is_empty() {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_web3_rs::types::U256;
+
+    use super::*;
+
+    fn push_all(aggregation: &LogsAggregation, tokens: Vec<Token>) -> Value {
+        let mut accumulator = LogsAccumulator::default();
+        for token in tokens {
+            accumulator.push(aggregation, Some(token)).unwrap();
+        }
+
+        accumulator.result(aggregation).unwrap()
+    }
+
+    #[test]
+    fn test_aggregations() {
+        let tokens = vec![
+            Token::Uint(U256::from(1)),
+            Token::Uint(U256::from(2)),
+            Token::Uint(U256::from(2)),
+        ];
+
+        assert_eq!(
+            push_all(&LogsAggregation::Count, tokens.clone()),
+            Value::from(3)
+        );
+        assert_eq!(
+            push_all(&LogsAggregation::Sum, tokens.clone()),
+            Value::from("5")
+        );
+        assert_eq!(
+            push_all(&LogsAggregation::Average, tokens.clone()),
+            Value::from("1.666666666666666666")
+        );
+        assert_eq!(
+            push_all(&LogsAggregation::Min, tokens.clone()),
+            Value::from("1")
+        );
+        assert_eq!(
+            push_all(&LogsAggregation::Max, tokens.clone()),
+            Value::from("2")
+        );
+        assert_eq!(push_all(&LogsAggregation::Last, tokens), Value::from("2"));
+    }
+
+    #[test]
+    fn test_signed_values() {
+        // -4 in two's complement
+        let minus_four = Token::Int(U256::MAX - U256::from(3));
+        let tokens = vec![minus_four, Token::Int(U256::from(2))];
+
+        assert_eq!(
+            push_all(&LogsAggregation::Sum, tokens.clone()),
+            Value::from("-2")
+        );
+        assert_eq!(
+            push_all(&LogsAggregation::Min, tokens.clone()),
+            Value::from("-4")
+        );
+        assert_eq!(
+            push_all(&LogsAggregation::Average, tokens),
+            Value::from("-1")
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        let accumulator = LogsAccumulator::default();
+
+        assert_eq!(
+            accumulator.result(&LogsAggregation::Count).unwrap(),
+            Value::from(0)
+        );
+        assert!(accumulator.result(&LogsAggregation::Max).is_err());
+    }
+
+    #[test]
+    fn test_divide() {
+        assert_eq!(divide(&BigInt::from(1), 4), "0.25");
+        assert_eq!(divide(&BigInt::from(-10), 4), "-2.5");
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, str::FromStr};
+
+use candid::CandidType;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::logs_aggregation::LogsAccumulator;
+use crate::STATE;
+
+const DEFAULT_CONFIRMATIONS: u64 = 12;
+const DEFAULT_MAX_BLOCKS_PER_FETCH: u64 = 1000;
+
+#[derive(Error, Debug)]
+pub enum LogsIndexError {
+    #[error("Invalid indexing config: {0}")]
+    InvalidConfig(String),
+    #[error("Stored index is corrupted: {0}")]
+    CorruptedIndex(String),
+}
+
+/// Incremental indexing of the event logs: only the blocks after the last processed one are fetched
+/// and folded into the aggregate kept in the canister state
+#[derive(Clone, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct LogsIndexing {
+    /// First block to index
+    pub start_block: u64,
+    /// Blocks behind the latest one which are not indexed yet to be safe from reorgs, 12 by default
+    pub confirmations: Option<u64>,
+    /// Maximum number of blocks fetched per request, 1000 by default
+    pub max_blocks_per_fetch: Option<u64>,
+}
+
+impl LogsIndexing {
+    pub fn validate(&self) -> Result<(), LogsIndexError> {
+        if self.max_blocks_per_fetch == Some(0) {
+            return Err(LogsIndexError::InvalidConfig(
+                "max_blocks_per_fetch should be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn confirmations(&self) -> u64 {
+        self.confirmations.unwrap_or(DEFAULT_CONFIRMATIONS)
+    }
+
+    pub fn max_blocks_per_fetch(&self) -> u64 {
+        self.max_blocks_per_fetch
+            .unwrap_or(DEFAULT_MAX_BLOCKS_PER_FETCH)
+    }
+}
+
+/// Block cursor and running aggregate of an indexed source
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct LogsIndex {
+    /// Next block to fetch
+    pub next_block: u64,
+    pub count: u64,
+    pub sum: String,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    /// JSON encoded field of the last log
+    pub last: Option<String>,
+}
+
+impl LogsIndex {
+    pub fn new(next_block: u64, accumulator: &LogsAccumulator) -> Self {
+        Self {
+            next_block,
+            count: accumulator.count,
+            sum: accumulator.sum.to_string(),
+            min: accumulator.min.as_ref().map(|min| min.to_string()),
+            max: accumulator.max.as_ref().map(|max| max.to_string()),
+            last: accumulator.last.as_ref().map(|last| last.to_string()),
+        }
+    }
+
+    pub fn accumulator(&self) -> Result<LogsAccumulator, LogsIndexError> {
+        let parse = |value: &str| {
+            BigInt::from_str(value).map_err(|err| LogsIndexError::CorruptedIndex(err.to_string()))
+        };
+
+        Ok(LogsAccumulator {
+            count: self.count,
+            sum: parse(&self.sum)?,
+            min: self.min.as_deref().map(parse).transpose()?,
+            max: self.max.as_deref().map(parse).transpose()?,
+            last: self
+                .last
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(|err| LogsIndexError::CorruptedIndex(err.to_string()))?,
+        })
+    }
+}
+
+/// Indexes of the sources, keyed by the hash of the source config,
+/// so any change of the source starts the indexing from scratch
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct LogsIndexes(HashMap<String, LogsIndex>);
+
+impl LogsIndexes {
+    pub fn get(key: &str) -> Option<LogsIndex> {
+        STATE.with(|state| state.borrow().logs_indexes.0.get(key).cloned())
+    }
+
+    /// Stores the index only if the stored cursor is still `expected_next_block`,
+    /// since the index could be advanced by a concurrent call while the logs were fetched
+    pub fn compare_and_set(key: &str, expected_next_block: Option<u64>, index: LogsIndex) -> bool {
+        STATE.with(|state| {
+            let indexes = &mut state.borrow_mut().logs_indexes.0;
+
+            let current_next_block = indexes.get(key).map(|index| index.next_block);
+            if current_next_block != expected_next_block {
+                return false;
+            }
+
+            indexes.insert(key.to_string(), index);
+            true
+        })
+    }
+
+    pub fn clear() {
+        STATE.with(|state| state.borrow_mut().logs_indexes.0.clear());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_index_roundtrip() {
+        let accumulator = LogsAccumulator {
+            count: 3,
+            sum: BigInt::from(-5),
+            min: Some(BigInt::from(-7)),
+            max: Some(BigInt::from(2)),
+            last: Some(Value::from("2")),
+        };
+
+        let index = LogsIndex::new(100, &accumulator);
+        assert_eq!(index.next_block, 100);
+
+        let restored = index.accumulator().unwrap();
+        assert_eq!(restored.count, 3);
+        assert_eq!(restored.sum, BigInt::from(-5));
+        assert_eq!(restored.min, Some(BigInt::from(-7)));
+        assert_eq!(restored.max, Some(BigInt::from(2)));
+        assert_eq!(restored.last, Some(Value::from("2")));
+    }
+}
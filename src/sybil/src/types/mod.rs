@@ -1,12 +1,23 @@
 pub mod balances;
 pub mod cache;
+pub mod certified_data;
 pub mod config;
 pub mod exchange_rate;
 pub mod feeds;
 pub mod http;
+pub mod key_rotation;
+pub mod logs_aggregation;
+pub mod logs_index;
 pub mod pagination;
 pub mod rate_data;
+pub mod response_format;
+pub mod rpc;
+pub mod rpc_wrapper;
+pub mod secrets;
+pub mod signer;
 pub mod source;
+pub mod source_health;
+pub mod source_templates;
 pub mod state;
 pub mod whitelist;
 
@@ -1,11 +1,16 @@
 use candid::CandidType;
 use ic_web3_rs::ethabi::Token;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 use crate::utils::encoding::encode_packed;
 
-use super::cache::{SignaturesCache, SignaturesCacheError};
+use super::{
+    cache::{SignaturesCache, SignaturesCacheError},
+    key_rotation::KeyRotation,
+    signer::Signer,
+};
 
 #[derive(Error, Debug)]
 pub enum RateDataError {
@@ -38,6 +43,17 @@ pub enum AssetData {
     },
 }
 
+impl AssetData {
+    /// Timestamp of the price feeds data
+    pub fn timestamp(&self) -> Option<u64> {
+        match self {
+            AssetData::DefaultPriceFeed { timestamp, .. }
+            | AssetData::CustomPriceFeed { timestamp, .. } => Some(*timestamp),
+            AssetData::CustomNumber { .. } | AssetData::CustomString { .. } => None,
+        }
+    }
+}
+
 impl Default for AssetData {
     fn default() -> Self {
         AssetData::DefaultPriceFeed {
@@ -53,9 +69,12 @@ impl Default for AssetData {
 pub struct AssetDataResult {
     pub data: AssetData,
     pub signature: Option<String>,
+    /// Signature made with the announced key during the key rotation grace period
+    pub next_signature: Option<String>,
 }
 
 impl AssetDataResult {
+    /// Payload of the signature, hashed according to the signature scheme before signing
     fn encode_packed(&self) -> Vec<u8> {
         let raw_data = match self.data.clone() {
             AssetData::DefaultPriceFeed {
@@ -101,13 +120,29 @@ impl AssetDataResult {
         encode_packed(&raw_data).expect("tokens should be valid")
     }
 
-    pub async fn sign(&mut self) -> Result<(), RateDataError> {
+    /// Hash of the data stored in the certified data tree
+    pub fn certified_hash(&self) -> [u8; 32] {
+        Sha256::digest(self.encode_packed()).into()
+    }
+
+    pub async fn sign(&mut self, signer: &Signer) -> Result<(), RateDataError> {
         let sign_data = self.encode_packed();
 
         self.signature = Some(hex::encode(
-            SignaturesCache::eth_sign_with_access(&sign_data).await?,
+            SignaturesCache::sign_with_access(&sign_data, signer).await?,
         ));
 
+        self.next_signature = match KeyRotation::pending() {
+            Some(next_key) => Some(hex::encode(
+                SignaturesCache::sign_with_access(
+                    &sign_data,
+                    &signer.with_key_name(next_key.key_name),
+                )
+                .await?,
+            )),
+            None => None,
+        };
+
         Ok(())
     }
 }
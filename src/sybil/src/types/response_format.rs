@@ -0,0 +1,372 @@
+use candid::CandidType;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+const DEFAULT_CSV_DELIMITER: u8 = b',';
+
+#[derive(Error, Debug)]
+pub enum ResponseFormatError {
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("Invalid CSV: {0}")]
+    InvalidCsv(#[from] csv::Error),
+    #[error("Invalid XML: {0}")]
+    InvalidXml(#[from] roxmltree::Error),
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(#[from] regex::Error),
+    #[error("Response is not a valid UTF-8 string")]
+    InvalidUtf8,
+    #[error("Invalid format config: {0}")]
+    InvalidConfig(String),
+    #[error("Value not found: {0}")]
+    ValueNotFound(String),
+}
+
+/// Format of the HTTP response body. For the non-JSON formats the extracted value
+/// is passed to the resolver as a string, so an empty pointer (`""`) selects it as is
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Csv(CsvFormat),
+    Xml(XmlFormat),
+    Text(TextFormat),
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct CsvFormat {
+    /// Single character delimiter, `,` by default
+    pub delimiter: Option<String>,
+    /// Whether the first row contains the column names, true by default
+    pub has_headers: Option<bool>,
+    /// Column name, or zero-based column index if there are no headers
+    pub column: String,
+    /// Zero-based index of the row among the matching rows, negative values count from the end.
+    /// The first row is used by default
+    pub row: Option<i64>,
+    /// Only the rows with `filter_value` in the `filter_column` are matching
+    pub filter_column: Option<String>,
+    pub filter_value: Option<String>,
+}
+
+/// Path to the element from the root, separated by `/`.
+/// A segment can select the element by an attribute: `Cube[currency=USD]`,
+/// the path can end with `@attribute` to return the attribute instead of the element text.
+/// Elements are matched by the local name, e.g. `Envelope/Cube/Cube/Cube[currency=USD]@rate`
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct XmlFormat {
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct TextFormat {
+    pub regex: String,
+    /// Capture group to extract, the first group or the whole match if there are no groups by default
+    pub group: Option<u64>,
+}
+
+impl ResponseFormat {
+    pub fn validate(&self) -> Result<(), ResponseFormatError> {
+        match self {
+            ResponseFormat::Json => Ok(()),
+            ResponseFormat::Csv(format) => format.delimiter().map(|_| ()),
+            ResponseFormat::Xml(format) => XmlPath::parse(&format.path).map(|_| ()),
+            ResponseFormat::Text(format) => {
+                let regex = Regex::new(&format.regex)?;
+                if format.group(&regex) >= regex.captures_len() {
+                    return Err(ResponseFormatError::InvalidConfig(
+                        "capture group does not exist".to_string(),
+                    ));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    pub fn extract(&self, body: &[u8]) -> Result<Value, ResponseFormatError> {
+        match self {
+            ResponseFormat::Json => Ok(serde_json::from_slice(body)?),
+            ResponseFormat::Csv(format) => format.extract(body).map(Value::String),
+            ResponseFormat::Xml(format) => format.extract(body).map(Value::String),
+            ResponseFormat::Text(format) => format.extract(body).map(Value::String),
+        }
+    }
+}
+
+impl CsvFormat {
+    fn delimiter(&self) -> Result<u8, ResponseFormatError> {
+        match &self.delimiter {
+            None => Ok(DEFAULT_CSV_DELIMITER),
+            Some(delimiter) if delimiter.len() == 1 => Ok(delimiter.as_bytes()[0]),
+            Some(_) => Err(ResponseFormatError::InvalidConfig(
+                "delimiter must be a single ASCII character".to_string(),
+            )),
+        }
+    }
+
+    fn extract(&self, body: &[u8]) -> Result<String, ResponseFormatError> {
+        let has_headers = self.has_headers.unwrap_or(true);
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter()?)
+            .has_headers(has_headers)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(body);
+
+        let headers = if has_headers {
+            Some(reader.headers()?.clone())
+        } else {
+            None
+        };
+
+        let column_index = |column: &str| -> Result<usize, ResponseFormatError> {
+            if let Some(index) = headers
+                .as_ref()
+                .and_then(|headers| headers.iter().position(|header| header == column))
+            {
+                return Ok(index);
+            }
+
+            column
+                .parse()
+                .map_err(|_| ResponseFormatError::ValueNotFound(format!("column {column}")))
+        };
+
+        let column = column_index(&self.column)?;
+        let filter = match (&self.filter_column, &self.filter_value) {
+            (Some(filter_column), Some(filter_value)) => {
+                Some((column_index(filter_column)?, filter_value))
+            }
+            _ => None,
+        };
+
+        let mut rows = vec![];
+        for record in reader.records() {
+            let record = record?;
+
+            let is_matching = match filter {
+                Some((index, value)) => record.get(index) == Some(value.as_str()),
+                None => true,
+            };
+
+            if is_matching {
+                rows.push(record);
+            }
+        }
+
+        let row = self.row.unwrap_or_default();
+        let row_index = if row < 0 {
+            rows.len().checked_sub(row.unsigned_abs() as usize)
+        } else {
+            Some(row as usize)
+        };
+
+        row_index
+            .and_then(|index| rows.get(index))
+            .and_then(|record| record.get(column))
+            .map(|field| field.to_string())
+            .ok_or_else(|| ResponseFormatError::ValueNotFound(format!("row {row}")))
+    }
+}
+
+struct XmlSegment {
+    name: String,
+    attribute: Option<(String, String)>,
+}
+
+struct XmlPath {
+    segments: Vec<XmlSegment>,
+    attribute: Option<String>,
+}
+
+impl XmlPath {
+    fn parse(path: &str) -> Result<Self, ResponseFormatError> {
+        let invalid_path =
+            || ResponseFormatError::InvalidConfig(format!("invalid xml path {path}"));
+
+        let (path, attribute) = match path.rsplit_once('@') {
+            Some((path, attribute)) => (path, Some(attribute.trim().to_string())),
+            None => (path, None),
+        };
+
+        let segments = path
+            .trim_matches('/')
+            .split('/')
+            .map(|segment| {
+                let segment = segment.trim();
+                let (name, attribute) = match segment.split_once('[') {
+                    Some((name, filter)) => {
+                        let (key, value) = filter
+                            .strip_suffix(']')
+                            .and_then(|filter| filter.split_once('='))
+                            .ok_or_else(invalid_path)?;
+
+                        let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+                        (name, Some((key.trim().to_string(), value.to_string())))
+                    }
+                    None => (segment, None),
+                };
+
+                if name.is_empty() {
+                    return Err(invalid_path());
+                }
+
+                Ok(XmlSegment {
+                    name: name.to_string(),
+                    attribute,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if attribute
+            .as_ref()
+            .is_some_and(|attribute| attribute.is_empty())
+        {
+            return Err(invalid_path());
+        }
+
+        Ok(Self {
+            segments,
+            attribute,
+        })
+    }
+
+    fn find<'a, 'input>(
+        segments: &[XmlSegment],
+        node: roxmltree::Node<'a, 'input>,
+    ) -> Option<roxmltree::Node<'a, 'input>> {
+        let (segment, rest) = segments.split_first()?;
+
+        let is_matching = node.is_element()
+            && node.tag_name().name() == segment.name
+            && match &segment.attribute {
+                Some((key, value)) => node.attribute(key.as_str()) == Some(value.as_str()),
+                None => true,
+            };
+
+        if !is_matching {
+            return None;
+        }
+
+        if rest.is_empty() {
+            return Some(node);
+        }
+
+        node.children().find_map(|child| Self::find(rest, child))
+    }
+}
+
+impl XmlFormat {
+    fn extract(&self, body: &[u8]) -> Result<String, ResponseFormatError> {
+        let path = XmlPath::parse(&self.path)?;
+        let text = std::str::from_utf8(body).map_err(|_| ResponseFormatError::InvalidUtf8)?;
+        let document = roxmltree::Document::parse(text)?;
+
+        let node = XmlPath::find(&path.segments, document.root_element())
+            .ok_or_else(|| ResponseFormatError::ValueNotFound(self.path.clone()))?;
+
+        let value = match &path.attribute {
+            Some(attribute) => node.attribute(attribute.as_str()),
+            None => node.text(),
+        };
+
+        value
+            .map(|value| value.trim().to_string())
+            .ok_or_else(|| ResponseFormatError::ValueNotFound(self.path.clone()))
+    }
+}
+
+impl TextFormat {
+    fn group(&self, regex: &Regex) -> usize {
+        match self.group {
+            Some(group) => group as usize,
+            None if regex.captures_len() > 1 => 1,
+            None => 0,
+        }
+    }
+
+    fn extract(&self, body: &[u8]) -> Result<String, ResponseFormatError> {
+        let regex = Regex::new(&self.regex)?;
+        let text = std::str::from_utf8(body).map_err(|_| ResponseFormatError::InvalidUtf8)?;
+
+        regex
+            .captures(text)
+            .and_then(|captures| captures.get(self.group(&regex)))
+            .map(|value| value.as_str().trim().to_string())
+            .ok_or_else(|| ResponseFormatError::ValueNotFound(self.regex.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv() {
+        let body =
+            b"date,currency,rate\n2024-01-01,USD,1.10\n2024-01-02,USD,1.11\n2024-01-02,GBP,0.86\n";
+
+        let format = CsvFormat {
+            column: "rate".to_string(),
+            row: Some(-1),
+            filter_column: Some("currency".to_string()),
+            filter_value: Some("USD".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(format.extract(body).unwrap(), "1.11");
+
+        let format = CsvFormat {
+            delimiter: Some(";".to_string()),
+            has_headers: Some(false),
+            column: "1".to_string(),
+            row: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(format.extract(b"a;1\nb;2\n").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_xml() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+                <gesmes:subject>Reference rates</gesmes:subject>
+                <Cube>
+                    <Cube time="2024-01-02">
+                        <Cube currency="USD" rate="1.0956"/>
+                        <Cube currency="JPY" rate="155.52"/>
+                    </Cube>
+                </Cube>
+            </gesmes:Envelope>"#;
+
+        let format = XmlFormat {
+            path: "Envelope/Cube/Cube/Cube[currency=JPY]@rate".to_string(),
+        };
+        assert_eq!(format.extract(body).unwrap(), "155.52");
+
+        let format = XmlFormat {
+            path: "Envelope/subject".to_string(),
+        };
+        assert_eq!(format.extract(body).unwrap(), "Reference rates");
+    }
+
+    #[test]
+    fn test_text() {
+        let body = b"Official rate: 92.35 RUB";
+
+        let format = TextFormat {
+            regex: r"rate: ([\d.]+)".to_string(),
+            group: None,
+        };
+        assert_eq!(format.extract(body).unwrap(), "92.35");
+
+        let format = ResponseFormat::Text(TextFormat {
+            regex: r"rate: ([\d.]+)".to_string(),
+            group: Some(2),
+        });
+        assert!(format.validate().is_err());
+    }
+}
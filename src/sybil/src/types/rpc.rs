@@ -0,0 +1,83 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::clone_with_state;
+
+const MAX_RPC_PROVIDERS: usize = 5;
+
+/// Transport of the EVM RPC calls
+#[derive(Clone, Copy, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum RpcTransport {
+    /// HTTPS outcalls straight to the provider
+    #[default]
+    Http,
+    /// Calls through the EVM RPC canister
+    EvmRpcCanister,
+}
+
+/// Defines when the responses of several providers are accepted
+#[derive(Clone, Copy, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum ConsensusPolicy {
+    /// Providers are called in order until the first successful response
+    #[default]
+    First,
+    /// More than half of the providers should return the same response.
+    /// The quantities which routinely differ across the providers, e.g. the block number or the gas price,
+    /// should only be returned by them, and raw transactions accepted by them
+    Majority,
+    /// All the providers should return the same response, with the same exceptions as `Majority`
+    All,
+}
+
+/// RPC settings of an EVM source
+#[derive(Clone, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct RpcCfg {
+    /// Transport from the canister config by default
+    pub transport: Option<RpcTransport>,
+    /// Other providers of the same chain, called along with the main rpc
+    pub providers: Option<Vec<String>>,
+    pub consensus: Option<ConsensusPolicy>,
+    /// Chain id, required to send raw transactions through the EVM RPC canister
+    pub chain_id: Option<u64>,
+    /// Whether the calls go through the rpc wrapper, true by default
+    pub use_rpc_wrapper: Option<bool>,
+}
+
+impl RpcCfg {
+    pub fn validate(&self) -> Result<(), String> {
+        let providers = self.providers.as_ref().map_or(0, Vec::len);
+        if providers + 1 > MAX_RPC_PROVIDERS {
+            return Err(format!(
+                "at most {MAX_RPC_PROVIDERS} providers are allowed, including the main rpc"
+            ));
+        }
+
+        if let Some(url) = self
+            .providers
+            .iter()
+            .flatten()
+            .find(|url| !validator::validate_url(url.as_str()))
+        {
+            return Err(format!("invalid provider url: {url}"));
+        }
+
+        Ok(())
+    }
+
+    pub fn transport(&self) -> RpcTransport {
+        self.transport
+            .or_else(|| clone_with_state!(rpc_transport))
+            .unwrap_or_default()
+    }
+
+    pub fn consensus(&self) -> ConsensusPolicy {
+        self.consensus.unwrap_or_default()
+    }
+
+    /// The main rpc followed by the other providers
+    pub fn urls(&self, rpc: &str) -> Vec<String> {
+        std::iter::once(rpc.to_string())
+            .chain(self.providers.iter().flatten().cloned())
+            .collect()
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use super::{Seconds, Timestamp};
+use crate::{clone_with_state, utils::time, STATE};
+
+const DEFAULT_CACHE_TTL: u64 = 30000; // 30 seconds
+/// Consecutive failures after which the wrapper is considered unhealthy
+const FAILURES_THRESHOLD: u32 = 3;
+/// Period after which an unhealthy wrapper is tried again
+const UNHEALTHY_PERIOD: Seconds = 300;
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct RpcWrapperHealth {
+    pub consecutive_failures: u32,
+    pub unhealthy_until: Option<Timestamp>,
+}
+
+/// Fallback proxies of the HTTP and EVM calls along with their health.
+/// The main wrapper is `State.rpc_wrapper`, the fallbacks are used in order when it is unhealthy
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct RpcWrappers {
+    pub fallbacks: Vec<String>,
+    /// `cacheTTL` parameter of the wrapper in milliseconds, 30 seconds by default
+    pub cache_ttl: Option<u64>,
+    pub health: HashMap<String, RpcWrapperHealth>,
+}
+
+impl RpcWrappers {
+    /// Configured wrappers, the healthy ones first in the configured order,
+    /// then the unhealthy ones starting from the one which recovers first
+    pub fn candidates() -> Vec<String> {
+        let main = clone_with_state!(rpc_wrapper);
+        let now = time::in_seconds();
+
+        STATE.with(|state| {
+            let state = state.borrow();
+            let wrappers = &state.rpc_wrappers;
+
+            let (healthy, mut unhealthy): (Vec<_>, Vec<_>) = std::iter::once(main)
+                .chain(wrappers.fallbacks.iter().cloned())
+                .filter(|wrapper| !wrapper.is_empty())
+                .map(|wrapper| {
+                    let unhealthy_until = wrappers
+                        .health
+                        .get(&wrapper)
+                        .and_then(|health| health.unhealthy_until)
+                        .filter(|until| *until > now);
+
+                    (wrapper, unhealthy_until)
+                })
+                .partition(|(_, unhealthy_until)| unhealthy_until.is_none());
+
+            unhealthy.sort_by_key(|(_, unhealthy_until)| *unhealthy_until);
+
+            healthy
+                .into_iter()
+                .chain(unhealthy)
+                .map(|(wrapper, _)| wrapper)
+                .collect()
+        })
+    }
+
+    pub fn wrap(wrapper: &str, url: &str) -> String {
+        let cache_ttl = STATE.with(|state| state.borrow().rpc_wrappers.cache_ttl);
+
+        format!(
+            "{}{}&cacheTTL={}",
+            wrapper,
+            urlencoding::encode(url),
+            cache_ttl.unwrap_or(DEFAULT_CACHE_TTL)
+        )
+    }
+
+    pub fn report_success(wrapper: &str) {
+        STATE.with(|state| {
+            state.borrow_mut().rpc_wrappers.health.remove(wrapper);
+        });
+    }
+
+    pub fn report_failure(wrapper: &str) {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let health = state
+                .rpc_wrappers
+                .health
+                .entry(wrapper.to_string())
+                .or_default();
+
+            health.consecutive_failures += 1;
+            if health.consecutive_failures >= FAILURES_THRESHOLD {
+                health.unhealthy_until = Some(time::in_seconds() + UNHEALTHY_PERIOD);
+            }
+        });
+    }
+
+    /// Replaces the fallbacks, the health of the removed wrappers is dropped
+    pub fn set_fallbacks(&mut self, main: &str, fallbacks: Vec<String>) {
+        self.health
+            .retain(|wrapper, _| wrapper == main || fallbacks.contains(wrapper));
+        self.fallbacks = fallbacks;
+    }
+}
@@ -0,0 +1,537 @@
+use std::{collections::HashMap, fmt};
+
+use candid::CandidType;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{source::Source, Address, Timestamp};
+use crate::{
+    utils::{time, vetkd},
+    SECRETS_KEY, STATE,
+};
+
+const NONCE_SIZE: usize = 12;
+const MAX_SECRET_NAME_LEN: usize = 64;
+const MAX_SECRET_VALUE_LEN: usize = 4096;
+const MAX_SECRETS_PER_OWNER: usize = 32;
+const VETKD_CONTEXT: &[u8] = b"sybil-secrets";
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("Secret not found: {0}")]
+    SecretNotFound(String),
+    #[error("Invalid secret name, only alphanumeric characters, '_' and '-' are allowed")]
+    InvalidName,
+    #[error("Secret value is empty or too long")]
+    InvalidValue,
+    #[error("Too many secrets, at most {MAX_SECRETS_PER_OWNER} are allowed per owner")]
+    TooManySecrets,
+    #[error("Unable to derive the secrets key: {0}")]
+    UnableToDeriveKey(String),
+    #[error("Unable to encrypt secret")]
+    EncryptionFailed,
+    #[error("Unable to decrypt secret")]
+    DecryptionFailed,
+}
+
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+struct EncryptedSecret {
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+    updated_at: Timestamp,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub name: String,
+    pub updated_at: Timestamp,
+}
+
+/// Named secrets of the feed owners, encrypted with ChaCha20-Poly1305.
+/// Sources reference the secrets by name, so a secret can be rotated without touching the feeds.
+/// The key is derived with vetKD and kept on the heap only, so it never gets into the state.
+#[derive(Clone, Default, CandidType, Serialize, Deserialize)]
+pub struct Secrets {
+    /// Random key stored by the previous versions, dropped once the secrets are re-encrypted
+    master_key: Option<Vec<u8>>,
+    /// vetKD key the secrets key is derived with, fixed on the first derivation
+    key_name: Option<String>,
+    nonce_counter: u64,
+    entries: HashMap<Address, HashMap<String, EncryptedSecret>>,
+}
+
+// Neither the keys nor the ciphertexts must get into the logs
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secrets")
+            .field("owners", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Secrets {
+    pub async fn set(owner: &Address, name: &str, value: &str) -> Result<(), SecretError> {
+        Self::validate(name, value)?;
+
+        let key = Self::key().await?;
+
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .secrets
+                .insert(&key, owner, name, value, time::in_seconds(), true)
+        })
+    }
+
+    pub async fn get(owner: &Address, name: &str) -> Result<String, SecretError> {
+        let key = Self::key().await?;
+
+        STATE.with(|state| state.borrow().secrets.read(&key, owner, name))
+    }
+
+    pub fn remove(owner: &Address, name: &str) -> Result<(), SecretError> {
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .secrets
+                .entries
+                .get_mut(owner)
+                .and_then(|secrets| secrets.remove(name))
+                .map(|_| ())
+                .ok_or_else(|| SecretError::SecretNotFound(name.to_string()))
+        })
+    }
+
+    pub fn list(owner: &Address) -> Vec<SecretInfo> {
+        STATE.with(|state| {
+            let state = state.borrow();
+            let mut secrets = state
+                .secrets
+                .entries
+                .get(owner)
+                .map(|secrets| {
+                    secrets
+                        .iter()
+                        .map(|(name, secret)| SecretInfo {
+                            name: name.clone(),
+                            updated_at: secret.updated_at,
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+
+            secrets.sort_by(|l, r| l.name.cmp(&r.name));
+            secrets
+        })
+    }
+
+    pub fn clear() {
+        STATE.with(|state| state.borrow_mut().secrets.entries.clear());
+    }
+
+    /// Moves the plaintext api keys of the sources into the owner secrets named
+    /// `{feed_id}-{source}-{title}`, the sources reference the secrets instead.
+    /// The owner limit is not applied to the keys of the already stored feeds.
+    pub async fn store_api_keys(
+        owner: &Address,
+        feed_id: &str,
+        sources: &mut [Source],
+        capped: bool,
+    ) -> Result<(), SecretError> {
+        let key = Self::key().await?;
+
+        STATE.with(|state| {
+            // the secrets are stored only if all of the keys are moved
+            let mut secrets = state.borrow().secrets.clone();
+
+            for (i, source) in sources.iter_mut().enumerate() {
+                let Source::HttpSource(http_source) = source else {
+                    continue;
+                };
+
+                for api_key in http_source.api_keys.iter_mut().flatten() {
+                    if api_key.secret.is_some() || api_key.key.is_empty() {
+                        continue;
+                    }
+
+                    let name = Self::api_key_name(feed_id, i, &api_key.title);
+                    Self::validate(&name, &api_key.key)?;
+                    secrets.insert(&key, owner, &name, &api_key.key, time::in_seconds(), capped)?;
+
+                    api_key.secret = Some(name);
+                    api_key.key = String::new();
+                }
+            }
+
+            state.borrow_mut().secrets = secrets;
+
+            Ok(())
+        })
+    }
+
+    /// Removes the secrets created by `store_api_keys` for the sources of the feed,
+    /// the secrets the owner has set and referenced by name are kept
+    pub fn remove_api_keys(owner: &Address, feed_id: &str, sources: &[Source]) {
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .secrets
+                .remove_feed_secrets(owner, feed_id, sources)
+        })
+    }
+
+    /// Returns the secrets key, deriving it on the first use after an install or upgrade
+    async fn key() -> Result<[u8; 32], SecretError> {
+        if let Some(key) = SECRETS_KEY.with(|key| *key.borrow()) {
+            return Ok(key);
+        }
+
+        let key_name = STATE.with(|state| {
+            let state = state.borrow();
+            state
+                .secrets
+                .key_name
+                .clone()
+                .unwrap_or_else(|| state.key_name.clone())
+        });
+
+        let key = vetkd::derive_symmetric_key(&key_name, VETKD_CONTEXT, &[])
+            .await
+            .map_err(|err| SecretError::UnableToDeriveKey(err.to_string()))?;
+
+        STATE.with(|state| {
+            let secrets = &mut state.borrow_mut().secrets;
+            // another call could have derived the key while waiting for the management canister
+            if secrets.key_name.is_none() {
+                secrets.key_name = Some(key_name);
+            }
+
+            secrets.reencrypt(&key)
+        })?;
+
+        SECRETS_KEY.with(|cached| *cached.borrow_mut() = Some(key));
+
+        Ok(key)
+    }
+
+    fn validate(name: &str, value: &str) -> Result<(), SecretError> {
+        if name.is_empty()
+            || name.len() > MAX_SECRET_NAME_LEN
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(SecretError::InvalidName);
+        }
+
+        if value.is_empty() || value.len() > MAX_SECRET_VALUE_LEN {
+            return Err(SecretError::InvalidValue);
+        }
+
+        Ok(())
+    }
+
+    fn api_key_name(feed_id: &str, source: usize, title: &str) -> String {
+        format!("{feed_id}-{source}-{title}")
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(MAX_SECRET_NAME_LEN)
+            .collect()
+    }
+
+    fn remove_feed_secrets(&mut self, owner: &Address, feed_id: &str, sources: &[Source]) {
+        let Some(secrets) = self.entries.get_mut(owner) else {
+            return;
+        };
+
+        for (i, source) in sources.iter().enumerate() {
+            let Source::HttpSource(http_source) = source else {
+                continue;
+            };
+
+            for api_key in http_source.api_keys.iter().flatten() {
+                let name = Self::api_key_name(feed_id, i, &api_key.title);
+                if api_key.secret.as_ref() == Some(&name) {
+                    secrets.remove(&name);
+                }
+            }
+        }
+
+        if secrets.is_empty() {
+            self.entries.remove(owner);
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: &[u8],
+        owner: &Address,
+        name: &str,
+        value: &str,
+        updated_at: Timestamp,
+        capped: bool,
+    ) -> Result<(), SecretError> {
+        let secrets = self.entries.entry(owner.clone()).or_default();
+        if capped && secrets.len() >= MAX_SECRETS_PER_OWNER && !secrets.contains_key(name) {
+            return Err(SecretError::TooManySecrets);
+        }
+
+        self.nonce_counter += 1;
+        let mut nonce = self.nonce_counter.to_le_bytes().to_vec();
+        nonce.resize(NONCE_SIZE, 0);
+
+        let ciphertext = Self::cipher(key)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: value.as_bytes(),
+                    aad: &Self::aad(owner, name),
+                },
+            )
+            .map_err(|_| SecretError::EncryptionFailed)?;
+
+        secrets.insert(
+            name.to_string(),
+            EncryptedSecret {
+                nonce,
+                ciphertext,
+                updated_at,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn read(&self, key: &[u8], owner: &Address, name: &str) -> Result<String, SecretError> {
+        let secret = self
+            .entries
+            .get(owner)
+            .and_then(|secrets| secrets.get(name))
+            .ok_or_else(|| SecretError::SecretNotFound(name.to_string()))?;
+
+        let value = Self::cipher(key)
+            .decrypt(
+                Nonce::from_slice(&secret.nonce),
+                Payload {
+                    msg: &secret.ciphertext,
+                    aad: &Self::aad(owner, name),
+                },
+            )
+            .map_err(|_| SecretError::DecryptionFailed)?;
+
+        String::from_utf8(value).map_err(|_| SecretError::DecryptionFailed)
+    }
+
+    /// Re-encrypts the secrets of the previous versions with the derived key
+    /// and drops the stored master key. Nothing is changed if any of them fails.
+    fn reencrypt(&mut self, key: &[u8]) -> Result<(), SecretError> {
+        let Some(master_key) = self.master_key.clone() else {
+            return Ok(());
+        };
+
+        let mut reencrypted = Secrets {
+            master_key: None,
+            key_name: self.key_name.clone(),
+            nonce_counter: self.nonce_counter,
+            entries: HashMap::new(),
+        };
+
+        for (owner, secrets) in &self.entries {
+            for (name, secret) in secrets {
+                let value = self.read(&master_key, owner, name)?;
+                reencrypted.insert(key, owner, name, &value, secret.updated_at, false)?;
+            }
+        }
+
+        *self = reencrypted;
+
+        Ok(())
+    }
+
+    fn cipher(key: &[u8]) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(key))
+    }
+
+    /// Binds the ciphertext to the owner and the name of the secret
+    fn aad(owner: &Address, name: &str) -> Vec<u8> {
+        format!("{owner}:{name}").into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::source::{ApiKey, HttpSource};
+
+    const KEY: [u8; 32] = [1; 32];
+    const OWNER: &str = "0x0000000000000000000000000000000000000001";
+
+    #[test]
+    fn test_insert_and_read() {
+        let mut secrets = Secrets::default();
+
+        secrets
+            .insert(&KEY, &OWNER.to_string(), "api", "value", 1, true)
+            .unwrap();
+        assert_eq!(
+            secrets.read(&KEY, &OWNER.to_string(), "api").unwrap(),
+            "value"
+        );
+
+        // rotation replaces the value and changes the nonce
+        let nonce = secrets.entries[OWNER]["api"].nonce.clone();
+        secrets
+            .insert(&KEY, &OWNER.to_string(), "api", "rotated", 2, true)
+            .unwrap();
+        assert_eq!(
+            secrets.read(&KEY, &OWNER.to_string(), "api").unwrap(),
+            "rotated"
+        );
+        assert_ne!(secrets.entries[OWNER]["api"].nonce, nonce);
+        assert_eq!(secrets.entries[OWNER].len(), 1);
+
+        assert!(matches!(
+            secrets.read(&[2; 32], &OWNER.to_string(), "api"),
+            Err(SecretError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            secrets.read(&KEY, &OWNER.to_string(), "other"),
+            Err(SecretError::SecretNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_aad_mismatch() {
+        let mut secrets = Secrets::default();
+        secrets
+            .insert(&KEY, &OWNER.to_string(), "api", "value", 1, true)
+            .unwrap();
+
+        // the ciphertext can't be reused under another name or owner
+        let secret = secrets.entries[OWNER]["api"].clone();
+        secrets
+            .entries
+            .get_mut(OWNER)
+            .unwrap()
+            .insert("copy".to_string(), secret.clone());
+        secrets
+            .entries
+            .entry("0x02".to_string())
+            .or_default()
+            .insert("api".to_string(), secret);
+
+        assert!(matches!(
+            secrets.read(&KEY, &OWNER.to_string(), "copy"),
+            Err(SecretError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            secrets.read(&KEY, &"0x02".to_string(), "api"),
+            Err(SecretError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_owner_limit() {
+        let mut secrets = Secrets::default();
+        let owner = OWNER.to_string();
+
+        for i in 0..MAX_SECRETS_PER_OWNER {
+            secrets
+                .insert(&KEY, &owner, &format!("api{i}"), "value", 1, true)
+                .unwrap();
+        }
+
+        assert!(matches!(
+            secrets.insert(&KEY, &owner, "extra", "value", 1, true),
+            Err(SecretError::TooManySecrets)
+        ));
+        // rotating an existing secret and uncapped inserts are still allowed
+        secrets
+            .insert(&KEY, &owner, "api0", "rotated", 1, true)
+            .unwrap();
+        secrets
+            .insert(&KEY, &owner, "extra", "value", 1, false)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_reencrypt() {
+        let legacy_key = [3; 32];
+        let mut secrets = Secrets {
+            master_key: Some(legacy_key.to_vec()),
+            ..Default::default()
+        };
+        secrets
+            .insert(&legacy_key, &OWNER.to_string(), "api", "value", 7, true)
+            .unwrap();
+
+        secrets.reencrypt(&KEY).unwrap();
+
+        assert!(secrets.master_key.is_none());
+        assert_eq!(
+            secrets.read(&KEY, &OWNER.to_string(), "api").unwrap(),
+            "value"
+        );
+        assert_eq!(secrets.entries[OWNER]["api"].updated_at, 7);
+        assert!(secrets
+            .read(&legacy_key, &OWNER.to_string(), "api")
+            .is_err());
+    }
+
+    #[test]
+    fn test_remove_feed_secrets() {
+        let mut secrets = Secrets::default();
+        let owner = OWNER.to_string();
+        for name in ["custom_eth-0-key", "custom_eth-1-key", "shared"] {
+            secrets
+                .insert(&KEY, &owner, name, "value", 1, true)
+                .unwrap();
+        }
+
+        let api_key = |secret: &str| ApiKey {
+            title: "key".to_string(),
+            secret: Some(secret.to_string()),
+            ..Default::default()
+        };
+        let sources = vec![
+            Source::HttpSource(HttpSource {
+                api_keys: Some(vec![api_key("custom_eth-0-key")]),
+                ..Default::default()
+            }),
+            Source::HttpSource(HttpSource {
+                api_keys: Some(vec![api_key("shared")]),
+                ..Default::default()
+            }),
+        ];
+
+        secrets.remove_feed_secrets(&owner, "custom_eth", &sources);
+
+        // only the secret created for the feed is removed
+        let mut names = secrets.entries[OWNER].keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["custom_eth-1-key", "shared"]);
+
+        // the feed of another owner doesn't touch the secrets
+        secrets.remove_feed_secrets(&"0x02".to_string(), "custom_eth", &sources);
+        assert_eq!(secrets.entries[OWNER].len(), 2);
+    }
+
+    #[test]
+    fn test_api_key_name() {
+        assert_eq!(
+            Secrets::api_key_name("custom_eth", 1, "X-API-Key value"),
+            "custom_eth-1-X-API-Key_value"
+        );
+        assert!(Secrets::api_key_name(&"a".repeat(100), 0, "key").len() <= MAX_SECRET_NAME_LEN);
+    }
+}
@@ -0,0 +1,284 @@
+use candid::CandidType;
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument,
+};
+use ic_web3_rs::{ic::get_eth_addr, signing::keccak256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    feeds::{Feed, FeedStorage},
+    Address,
+};
+use crate::{
+    clone_with_state,
+    utils::{
+        address,
+        canister::{self, CanisterError},
+        signature::{self, SchnorrAlgorithm, SchnorrKeyId, SchnorrPublicKeyArgument},
+    },
+    STATE,
+};
+
+const OWNER_DERIVATION_LABEL: &[u8] = b"owner";
+const FEED_DERIVATION_LABEL: &[u8] = b"feed";
+
+#[derive(Error, Debug)]
+pub enum SignerError {
+    #[error("Feed not found")]
+    FeedNotFound,
+    #[error("Signer key is not derived yet")]
+    AddressNotDerived,
+    #[error("Owner has no balance")]
+    UnknownOwner,
+}
+
+/// Defines which derivation path is used to sign the data of a feed
+#[derive(Clone, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum SignerScope {
+    /// Canister-wide key, shared between all the feeds
+    #[default]
+    Canister,
+    /// Key derived from the feed owner address
+    Owner,
+    /// Key derived from the feed id
+    Feed,
+}
+
+/// Threshold signature scheme used to sign the data of a feed
+#[derive(Clone, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum SignatureScheme {
+    /// secp256k1 ECDSA over the keccak256 hash, verifiable with `ecrecover`
+    #[default]
+    Ecdsa,
+    /// BIP340 Schnorr over the sha256 hash, used by Bitcoin Taproot
+    SchnorrBip340,
+    /// Ed25519 over the raw payload, used by Solana and other non-EVM chains
+    Ed25519,
+}
+
+impl SignatureScheme {
+    pub fn schnorr_algorithm(&self) -> Option<SchnorrAlgorithm> {
+        match self {
+            SignatureScheme::Ecdsa => None,
+            SignatureScheme::SchnorrBip340 => Some(SchnorrAlgorithm::Bip340Secp256k1),
+            SignatureScheme::Ed25519 => Some(SchnorrAlgorithm::Ed25519),
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum SignerRequest {
+    Canister,
+    Owner(Address),
+    Feed(String),
+}
+
+/// Threshold key, derivation path and scheme used to produce signatures
+#[derive(Clone, Debug)]
+pub struct Signer {
+    pub key_name: String,
+    pub derivation_path: Vec<Vec<u8>>,
+    pub scheme: SignatureScheme,
+}
+
+impl Signer {
+    pub fn canister() -> Self {
+        Self {
+            key_name: clone_with_state!(key_name),
+            derivation_path: vec![ic_cdk::id().as_slice().to_vec()],
+            scheme: SignatureScheme::Ecdsa,
+        }
+    }
+
+    pub fn owner(owner: &Address) -> Self {
+        Self {
+            key_name: clone_with_state!(key_name),
+            derivation_path: vec![
+                ic_cdk::id().as_slice().to_vec(),
+                OWNER_DERIVATION_LABEL.to_vec(),
+                owner.to_lowercase().into_bytes(),
+            ],
+            scheme: SignatureScheme::Ecdsa,
+        }
+    }
+
+    pub fn feed(id: &str) -> Self {
+        Self {
+            key_name: clone_with_state!(key_name),
+            derivation_path: vec![
+                ic_cdk::id().as_slice().to_vec(),
+                FEED_DERIVATION_LABEL.to_vec(),
+                id.as_bytes().to_vec(),
+            ],
+            scheme: SignatureScheme::Ecdsa,
+        }
+    }
+
+    pub fn for_feed(feed: &Feed) -> Self {
+        let signer = match feed.signer_scope.clone().unwrap_or_default() {
+            SignerScope::Canister => Self::canister(),
+            SignerScope::Owner => Self::owner(&feed.owner),
+            SignerScope::Feed => Self::feed(&feed.id),
+        };
+
+        signer.with_scheme(feed.signature_scheme.clone().unwrap_or_default())
+    }
+
+    pub fn for_request(req: &SignerRequest) -> Result<Self, SignerError> {
+        match req {
+            SignerRequest::Canister => Ok(Self::canister()),
+            SignerRequest::Owner(owner) => Ok(Self::owner(owner)),
+            SignerRequest::Feed(id) => FeedStorage::get(id)
+                .map(|feed| Self::for_feed(&feed))
+                .ok_or(SignerError::FeedNotFound),
+        }
+    }
+
+    pub fn with_key_name(mut self, key_name: String) -> Self {
+        self.key_name = key_name;
+        self
+    }
+
+    pub fn with_scheme(mut self, scheme: SignatureScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Whether the signer is the active canister-wide ECDSA key
+    pub fn is_canister(&self) -> bool {
+        self.scheme == SignatureScheme::Ecdsa
+            && self.derivation_path == vec![ic_cdk::id().as_slice().to_vec()]
+            && self.key_name == clone_with_state!(key_name)
+    }
+
+    /// Unique identifier of the key, used as a cache key for derived data
+    pub fn cache_key(&self) -> String {
+        let mut data = self.key_name.as_bytes().to_vec();
+        for part in &self.derivation_path {
+            data.extend(keccak256(part));
+        }
+
+        if let Some(algorithm) = self.scheme.schnorr_algorithm() {
+            data.extend(format!("{algorithm:?}").as_bytes());
+        }
+
+        hex::encode(keccak256(&data))
+    }
+
+    /// Returns the hex encoded public key of the signer if it was already derived
+    pub fn cached_public_key(&self) -> Option<String> {
+        STATE.with(|state| {
+            state
+                .borrow()
+                .signer_public_keys
+                .get(&self.cache_key())
+                .cloned()
+        })
+    }
+
+    /// Returns the hex encoded public key of the signer:
+    /// SEC1 compressed key for ECDSA and BIP340, 32 bytes key for Ed25519
+    pub async fn public_key(&self) -> Result<String, CanisterError> {
+        if let Some(public_key) = self.cached_public_key() {
+            return Ok(public_key);
+        }
+
+        let public_key = match self.scheme.schnorr_algorithm() {
+            Some(algorithm) => {
+                signature::schnorr_public_key(SchnorrPublicKeyArgument {
+                    canister_id: None,
+                    derivation_path: self.derivation_path.clone(),
+                    key_id: SchnorrKeyId {
+                        algorithm,
+                        name: self.key_name.clone(),
+                    },
+                })
+                .await
+                .map_err(|(_, msg)| CanisterError::UnableToGetPublicKey(msg))?
+                .0
+                .public_key
+            }
+            None => {
+                ecdsa_public_key(EcdsaPublicKeyArgument {
+                    canister_id: None,
+                    derivation_path: self.derivation_path.clone(),
+                    key_id: EcdsaKeyId {
+                        curve: EcdsaCurve::Secp256k1,
+                        name: self.key_name.clone(),
+                    },
+                })
+                .await
+                .map_err(|(_, msg)| CanisterError::UnableToGetPublicKey(msg))?
+                .0
+                .public_key
+            }
+        };
+
+        let public_key = hex::encode(public_key);
+
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .signer_public_keys
+                .insert(self.cache_key(), public_key.clone());
+        });
+
+        Ok(public_key)
+    }
+
+    /// Derives the key in advance, so it can be obtained with a query.
+    /// The public key is cached for every scheme, the address for ECDSA as well
+    pub async fn derive(&self) -> Result<String, CanisterError> {
+        let public_key = self.public_key().await?;
+        if self.scheme == SignatureScheme::Ecdsa {
+            self.eth_address().await?;
+        }
+
+        Ok(public_key)
+    }
+
+    /// Returns the address of the signer if it was already derived
+    pub fn cached_eth_address(&self) -> Option<Address> {
+        if self.is_canister() {
+            return clone_with_state!(eth_address);
+        }
+
+        STATE.with(|state| {
+            state
+                .borrow()
+                .signer_addresses
+                .get(&self.cache_key())
+                .cloned()
+        })
+    }
+
+    pub async fn eth_address(&self) -> Result<Address, CanisterError> {
+        if self.is_canister() {
+            return canister::eth_address().await;
+        }
+
+        if let Some(address) = self.cached_eth_address() {
+            return Ok(address);
+        }
+
+        let raw_address = get_eth_addr(
+            None,
+            Some(self.derivation_path.clone()),
+            self.key_name.clone(),
+        )
+        .await
+        .map_err(CanisterError::UnableToGetEthAddress)?;
+
+        let formatted_address = address::from_h160(&raw_address)?;
+
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .signer_addresses
+                .insert(self.cache_key(), formatted_address.clone());
+        });
+
+        Ok(formatted_address)
+    }
+}
@@ -1,38 +1,115 @@
 use std::str::FromStr;
 
-use crate::utils::{validation, web3};
-use candid::CandidType;
-use ic_cdk::api::management_canister::http_request::{CanisterHttpRequestArgument, HttpHeader};
+use crate::utils::{
+    abi::{self, AbiError, EventFieldPath},
+    address::{self, AddressError},
+    bitcoin::{self, BitcoinError},
+    candid_value::{self, CandidValueError},
+    nat,
+    resolver::Resolver,
+    retry::{retry_with_attempts, Attempts, Reject, RetryPolicy},
+    time, validation, web3,
+};
+use candid::{utils::ArgumentEncoder, CandidType, Nat, Principal};
+use ic_cdk::api::management_canister::{
+    bitcoin::{BitcoinNetwork, Satoshi},
+    http_request::{CanisterHttpRequestArgument, HttpHeader, HttpMethod},
+};
 use ic_web3_rs::{
-    ethabi::{Contract, RawLog, Token},
-    types::{H160, H256},
+    ethabi::{
+        token::{LenientTokenizer, Tokenizer},
+        Contract, Event, RawLog, Token,
+    },
+    futures::future::join_all,
+    signing::keccak256,
+    types::{BlockNumber, Log, H160, H256, U256},
+    Transport,
 };
-use jsonptr::{Pointer, Resolve};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 use validator::{Validate, ValidationErrors};
 
-use crate::{
-    clone_with_state, defer, jobs::cache_cleaner, retry_until_success, types::cache::HttpCache,
-};
+use crate::{clone_with_state, defer, jobs::cache_cleaner, types::cache::HttpCache};
 
-use super::{cache::HttpCacheError, feeds::RateResult, Seconds};
+use super::{
+    cache::HttpCacheError,
+    feeds::RateResult,
+    logs_aggregation::{LogsAccumulator, LogsAggregation, LogsAggregationError},
+    logs_index::{LogsIndex, LogsIndexError, LogsIndexes, LogsIndexing},
+    response_format::{ResponseFormat, ResponseFormatError},
+    rpc::RpcCfg,
+    rpc_wrapper::RpcWrappers,
+    secrets::{SecretError, Secrets},
+    Address, Seconds,
+};
 
-const ORALLY_WRAPPER_CAHCHE_TTL: u64 = 30000; // 30 seconds
 const MIN_EXPECTED_BYTES: u64 = 1;
 const MAX_EXPECTED_BYTES: u64 = 1024 * 1024 * 2;
+const MAX_EVM_ADDRESSES: u64 = 10;
+/// Attempts of the calls made for one rate, across the rpc wrappers, the providers and the retries
+const MAX_SOURCE_ATTEMPTS: u32 = 20;
+/// Candid encoding of no arguments
+const EMPTY_CANDID_ARGS: &[u8] = b"DIDL\x00\x00";
+/// Read-only methods allowed in the canister call sources unless `Cfg.canister_call_methods` is set
+const DEFAULT_CANISTER_CALL_METHODS: [&str; 7] = [
+    "icrc1_balance_of",
+    "icrc1_total_supply",
+    "icrc1_fee",
+    "icrc1_decimals",
+    "icrc1_metadata",
+    "icrc1_name",
+    "icrc1_symbol",
+];
+const MAX_ICRC1_ACCOUNTS: u64 = 10;
+const MAX_BITCOIN_ADDRESSES: u64 = 10;
+const BITCOIN_DECIMALS: u32 = 8;
+/// Cycles of a response byte of an HTTPS outcall on a 13 nodes subnet
+const OUTCALL_CYCLES_PER_BYTE: u128 = 800;
+const MAX_EXCERPT_BYTES: usize = 1024;
 
-#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
+/// Defines where the api key is injected into the request
+#[derive(Clone, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum ApiKeyPlacement {
+    /// `{title}` placeholders in the uri, headers and body are replaced with the key
+    #[default]
+    Uri,
+    /// The key is sent as the value of the header with the given name,
+    /// `{title}` placeholders in the headers and body are replaced with the key
+    Header(String),
+    /// `{title}` placeholders in the headers and body are replaced with the key
+    Body,
+}
+
+#[derive(Clone, Default, CandidType, Serialize, Deserialize, Validate)]
 pub struct ApiKey {
     pub title: String,
     pub key: String,
+    pub placement: Option<ApiKeyPlacement>,
+    /// Name of the owner secret used instead of `key`, see `set_secret`
+    pub secret: Option<String>,
 }
 
 impl ApiKey {
     pub fn censor(&mut self) {
         self.key = "***".to_string();
     }
+
+    pub fn placement(&self) -> ApiKeyPlacement {
+        self.placement.clone().unwrap_or_default()
+    }
+}
+
+// Keys must never get into the logs
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKey")
+            .field("title", &self.title)
+            .field("key", &"***")
+            .field("placement", &self.placement)
+            .field("secret", &self.secret)
+            .finish()
+    }
 }
 
 #[derive(Error, Debug)]
@@ -55,12 +132,51 @@ pub enum SourceError {
     SerdeError(#[from] serde_json::Error),
     #[error("Web3 error: {0}")]
     Web3Error(#[from] web3::Web3Error),
+    #[error("Secret error: {0}")]
+    SecretError(#[from] SecretError),
+    #[error("Response format error: {0}")]
+    ResponseFormatError(#[from] ResponseFormatError),
+    #[error("Address error: {0}")]
+    AddressError(#[from] AddressError),
+    #[error("Failed to encode call: {0}")]
+    FailedToEncodeCall(String),
+    #[error("Failed to decode output: {0}")]
+    FailedToDecodeOutput(String),
+    #[error("Logs aggregation error: {0}")]
+    LogsAggregationError(#[from] LogsAggregationError),
+    #[error("Logs index error: {0}")]
+    LogsIndexError(#[from] LogsIndexError),
+    #[error("ABI error: {0}")]
+    AbiError(#[from] AbiError),
+    #[error("Canister call failed: {0}")]
+    CanisterCallFailed(String),
+    #[error("Canister call is not allowed: {0}")]
+    CanisterCallNotAllowed(String),
+    #[error("Candid value error: {0}")]
+    CandidValueError(#[from] CandidValueError),
+    #[error("Bitcoin error: {0}")]
+    BitcoinError(#[from] BitcoinError),
+    #[error("Source is quarantined")]
+    Quarantined,
+    /// The response was fetched but couldn't be resolved, keeps the beginning of the response
+    #[error("{error}")]
+    UnresolvedResponse {
+        excerpt: String,
+        bytes: usize,
+        error: Box<SourceError>,
+    },
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub enum Source {
     HttpSource(HttpSource),
     EvmEventLogsSource(EvmEventLogsSource),
+    EvmContractCallSource(EvmContractCallSource),
+    EvmStorageSource(EvmStorageSource),
+    EvmBalanceSource(EvmBalanceSource),
+    CanisterCallSource(CanisterCallSource),
+    Icrc1Source(Icrc1Source),
+    BitcoinSource(BitcoinSource),
 }
 
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
@@ -71,72 +187,787 @@ pub struct EvmEventLogsSource {
     pub to_block: Option<u64>,
     pub address: Option<String>,
     pub topic: Option<String>,
+    pub topic1: Option<String>,
+    pub topic2: Option<String>,
+    pub topic3: Option<String>,
     pub block_hash: Option<String>,
+    /// Block range relative to the latest block,
+    /// can't be used along with `from_block`, `to_block` and `block_hash`
+    pub range: Option<BlockRange>,
+    /// Index of the log, logs are ordered by block number, transaction index and log index.
+    /// Not used if `aggregation` is set
     pub log_index: u32,
+    /// Aggregation over the fields of all the matching logs of the event
+    pub aggregation: Option<LogsAggregation>,
+    /// Incremental indexing of the logs, requires `aggregation`
+    /// and can't be used along with `from_block`, `to_block`, `block_hash` and `range`
+    pub indexing: Option<LogsIndexing>,
+    pub rpc_cfg: Option<RpcCfg>,
+    /// Retries of the transient rpc failures
+    pub retry_policy: Option<RetryPolicy>,
+    /// Name of the event parameter, or a path to a nested field, e.g. `order.amounts[1]`
     pub event_log_field_name: String,
     pub event_name: String,
     pub event_abi: String,
 }
 
+#[derive(Clone, Debug, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum BlockRange {
+    /// Logs of the latest block
+    Latest,
+    /// Logs of the last N blocks, including the latest one
+    LastBlocks(u64),
+}
+
+impl EvmEventLogsSource {
+    /// Decodes the log and returns the value of the field
+    fn log_field(event: &Event, field: &EventFieldPath, log: Log) -> Result<Token, SourceError> {
+        let raw_log = RawLog {
+            topics: log.topics,
+            data: log.data.0,
+        };
+
+        let log = event
+            .parse_log(raw_log)
+            .map_err(|err| SourceError::FailedToParseLogs(err.to_string()))?;
+
+        let token = log
+            .params
+            .into_iter()
+            .find(|p| p.name == field.param())
+            .ok_or(SourceError::LogFieldNotFound(field.param().to_string()))?
+            .value;
+
+        Ok(field.resolve(token)?)
+    }
+
+    /// Folds the logs of the event into the accumulator, logs should be in the chain order
+    fn aggregate_logs(
+        aggregation: &LogsAggregation,
+        event: &Event,
+        field: &EventFieldPath,
+        logs: Vec<Log>,
+        accumulator: &mut LogsAccumulator,
+    ) -> Result<(), SourceError> {
+        let signature = event.signature();
+
+        // only the logs of the event are aggregated
+        for log in logs {
+            if !event.anonymous && log.topics.first() != Some(&signature) {
+                continue;
+            }
+
+            let field = match aggregation {
+                LogsAggregation::Count => None,
+                _ => Some(Self::log_field(event, field, log)?),
+            };
+
+            accumulator.push(aggregation, field)?;
+        }
+
+        Ok(())
+    }
+
+    /// Event of the ABI and the path of its field, the first segment of the path should name an event parameter
+    fn event_field(&self) -> Result<(Event, EventFieldPath), SourceError> {
+        let contract = Contract::load(self.event_abi.as_bytes())
+            .map_err(|err| SourceError::FailedToParseABI(err.to_string()))?;
+
+        let event = contract
+            .event(&self.event_name)
+            .map_err(|err| SourceError::FailedToParseABI(err.to_string()))?;
+
+        let field = EventFieldPath::new(
+            &self.event_log_field_name,
+            &self.event_abi,
+            &self.event_name,
+        )?;
+
+        if !event.inputs.iter().any(|input| input.name == field.param()) {
+            return Err(SourceError::LogFieldNotFound(field.param().to_string()));
+        }
+
+        Ok((event.clone(), field))
+    }
+
+    /// Key of the source index, made of the fields defining the indexed logs and their aggregation,
+    /// so the providers, the retries or the indexing pace can change without resetting the index
+    fn index_key(&self, indexing: &LogsIndexing) -> Result<String, SourceError> {
+        let lowercase = |hex: &Option<String>| hex.as_ref().map(|hex| hex.to_lowercase());
+
+        let stream = serde_json::json!({
+            "rpc": self.rpc,
+            "chain_id": self.rpc_cfg.as_ref().and_then(|rpc_cfg| rpc_cfg.chain_id),
+            "address": lowercase(&self.address),
+            "topics": [
+                lowercase(&self.topic),
+                lowercase(&self.topic1),
+                lowercase(&self.topic2),
+                lowercase(&self.topic3),
+            ],
+            "event_name": self.event_name,
+            "event_abi": self.event_abi,
+            "field": self.event_log_field_name,
+            "aggregation": self.aggregation,
+            "start_block": indexing.start_block,
+        });
+
+        Ok(hex::encode(keccak256(&serde_json::to_vec(&stream)?)))
+    }
+}
+
+/// Reads the output of a view function with `eth_call`
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
+pub struct EvmContractCallSource {
+    #[validate(url)]
+    pub rpc: String,
+    pub address: String,
+    /// JSON ABI containing the function
+    pub abi: String,
+    pub function_name: String,
+    /// Function arguments in the human-readable form, e.g. `0x...` for addresses and bytes,
+    /// decimal numbers, `true`/`false`, `[1,2]` for arrays and `(1,0x...)` for tuples
+    pub args: Option<Vec<String>>,
+    /// Index of the function output to use, the first one by default
+    pub output_index: Option<u32>,
+    /// Block number to call the function at, the latest block by default
+    pub block: Option<u64>,
+    pub rpc_cfg: Option<RpcCfg>,
+}
+
+/// Reads a storage slot of the contracts with `eth_getStorageAt`,
+/// the slot values of all the addresses are summed as uint256
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
+pub struct EvmStorageSource {
+    #[validate(url)]
+    pub rpc: String,
+    #[validate(length(min = 1, max = "MAX_EVM_ADDRESSES"))]
+    pub addresses: Vec<String>,
+    /// Slot index, `0x` prefixed hex or decimal
+    pub slot: String,
+    /// Block number to read the storage at, the latest block by default
+    pub block: Option<u64>,
+    pub rpc_cfg: Option<RpcCfg>,
+}
+
+/// Sums the native or ERC20 token balances of the addresses
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
+pub struct EvmBalanceSource {
+    #[validate(url)]
+    pub rpc: String,
+    #[validate(length(min = 1, max = "MAX_EVM_ADDRESSES"))]
+    pub addresses: Vec<String>,
+    /// ERC20 token contract, the native balance is used if not set
+    pub token: Option<String>,
+    /// Block number to read the balances at, the latest block by default
+    pub block: Option<u64>,
+    pub rpc_cfg: Option<RpcCfg>,
+}
+
+/// Calls a read-only method of an allowed canister, see `Cfg.canister_call_canisters`
+/// and `Cfg.canister_call_methods`. Query methods are called as update calls under the sybil identity
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, Validate)]
+pub struct CanisterCallSource {
+    pub canister_id: Principal,
+    #[validate(length(min = 1))]
+    pub method: String,
+    /// Candid encoded arguments, no arguments by default
+    pub args: Option<Vec<u8>>,
+    /// Path to the value in the first reply value, e.g. `Ok.reserves.1`, see `candid_value::resolve_reply`.
+    /// The whole value is used if not set
+    pub path: Option<String>,
+}
+
+/// Reads the metrics of an ICRC-1 ledger, amounts are normalized by the ledger decimals
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Icrc1Source {
+    pub ledger: Principal,
+    pub metric: Icrc1Metric,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum Icrc1Metric {
+    TotalSupply,
+    Fee,
+    /// Sum of the balances of the accounts
+    BalanceOf(Vec<Icrc1Account>),
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Icrc1Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+/// Sums the balances of the bitcoin addresses, the result is in BTC
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, Validate)]
+pub struct BitcoinSource {
+    pub network: BitcoinNetwork,
+    #[validate(length(min = 1, max = "MAX_BITCOIN_ADDRESSES"))]
+    pub addresses: Vec<String>,
+    pub min_confirmations: Option<u32>,
+    /// `bitcoin_get_balance` is used by default
+    pub mode: Option<BitcoinSourceMode>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub enum BitcoinSourceMode {
+    #[default]
+    Balance,
+    /// Sum of the utxos values from `bitcoin_get_utxos`
+    Utxos,
+}
+
 #[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize, Validate)]
 pub struct HttpSource {
     #[validate(url)]
     pub uri: String,
     pub api_keys: Option<Vec<ApiKey>>,
-    #[validate(regex = "validation::RATE_RESOLVER")]
+    /// JSON pointer or JSONPath with optional post-processing, see `Resolver`
+    #[validate(custom = "validation::validate_resolver")]
     pub resolver: String,
     #[validate(range(min = "MIN_EXPECTED_BYTES", max = "MAX_EXPECTED_BYTES"))]
     pub expected_bytes: Option<u64>,
+    /// HTTP method, GET by default
+    pub method: Option<HttpMethod>,
+    /// Request body template, `{title}` placeholders are replaced with the api keys
+    pub body: Option<String>,
+    /// Request headers templates, override the default headers with the same name
+    #[validate(custom = "validation::validate_http_headers")]
+    pub headers: Option<Vec<HttpHeader>>,
+    /// Response body format, JSON by default
+    #[validate(custom = "validation::validate_response_format")]
+    pub format: Option<ResponseFormat>,
+    /// Whether the request goes through the rpc wrapper, true by default
+    pub use_rpc_wrapper: Option<bool>,
+    /// Retries of the transient outcall failures
+    pub retry_policy: Option<RetryPolicy>,
 }
 
 impl HttpSource {
-    pub fn get_url_with_keys(&self) -> String {
-        let mut url = self.uri.clone();
+    /// Extracts the value from the response body by the format and the resolver
+    fn resolve(&self, body: &[u8]) -> Result<Value, SourceError> {
+        let resolver = Resolver::from_str(&self.resolver)
+            .map_err(|err| HttpCacheError::InvalidResponseBodyResolver(err.to_string()))?;
 
-        if let Some(api_keys) = &self.api_keys {
-            for api_key in api_keys {
-                url = url.replace(&format!("{{{}}}", &api_key.title), &api_key.key);
+        let data = self.format.clone().unwrap_or_default().extract(body)?;
+
+        Ok(resolver
+            .resolve(&data)
+            .map_err(|err| HttpCacheError::InvalidResponseBodyResolver(err.to_string()))?)
+    }
+
+    fn fill_keys(&self, template: &str, in_uri: bool) -> String {
+        let mut result = template.to_string();
+
+        for api_key in self.api_keys.iter().flatten() {
+            if in_uri && api_key.placement() != ApiKeyPlacement::Uri {
+                continue;
             }
+
+            result = result.replace(&format!("{{{}}}", &api_key.title), &api_key.key);
+        }
+
+        result
+    }
+
+    pub fn get_url_with_keys(&self) -> String {
+        self.fill_keys(&self.uri, true)
+    }
+
+    pub fn get_body_with_keys(&self) -> Option<Vec<u8>> {
+        self.body
+            .as_ref()
+            .map(|body| self.fill_keys(body, false).into_bytes())
+    }
+
+    pub fn get_headers_with_keys(&self) -> Vec<HttpHeader> {
+        let mut headers = Source::get_default_headers();
+
+        let custom_headers = self
+            .headers
+            .iter()
+            .flatten()
+            .map(|header| HttpHeader {
+                name: header.name.clone(),
+                value: self.fill_keys(&header.value, false),
+            })
+            .chain(self.api_keys.iter().flatten().filter_map(
+                |api_key| match api_key.placement() {
+                    ApiKeyPlacement::Header(name) => Some(HttpHeader {
+                        name,
+                        value: api_key.key.clone(),
+                    }),
+                    _ => None,
+                },
+            ));
+
+        for header in custom_headers {
+            headers.retain(|h| !h.name.eq_ignore_ascii_case(&header.name));
+            headers.push(header);
         }
 
-        url
+        headers
+    }
+
+    pub fn get_method(&self) -> HttpMethod {
+        self.method.unwrap_or(HttpMethod::GET)
+    }
+}
+
+impl SourceError {
+    /// Name of the error variant, used in the structured previews
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SourceError::FailedToGetLogs(_) => "FailedToGetLogs",
+            SourceError::FailedToParseLogs(_) => "FailedToParseLogs",
+            SourceError::FailedToParseABI(_) => "FailedToParseABI",
+            SourceError::ValidationError(_) => "ValidationError",
+            SourceError::InvalidRequest(_) => "InvalidRequest",
+            SourceError::LogFieldNotFound(_) => "LogFieldNotFound",
+            SourceError::HttpCacheError(_) => "HttpCacheError",
+            SourceError::SerdeError(_) => "SerdeError",
+            SourceError::Web3Error(_) => "Web3Error",
+            SourceError::SecretError(_) => "SecretError",
+            SourceError::ResponseFormatError(_) => "ResponseFormatError",
+            SourceError::AddressError(_) => "AddressError",
+            SourceError::FailedToEncodeCall(_) => "FailedToEncodeCall",
+            SourceError::FailedToDecodeOutput(_) => "FailedToDecodeOutput",
+            SourceError::LogsAggregationError(_) => "LogsAggregationError",
+            SourceError::LogsIndexError(_) => "LogsIndexError",
+            SourceError::AbiError(_) => "AbiError",
+            SourceError::CanisterCallFailed(_) => "CanisterCallFailed",
+            SourceError::CanisterCallNotAllowed(_) => "CanisterCallNotAllowed",
+            SourceError::CandidValueError(_) => "CandidValueError",
+            SourceError::BitcoinError(_) => "BitcoinError",
+            SourceError::Quarantined => "Quarantined",
+            SourceError::UnresolvedResponse { error, .. } => error.kind(),
+        }
+    }
+
+    /// Bytes of the response fetched before the failure, they are charged as well
+    pub fn fetched_bytes(&self) -> usize {
+        match self {
+            SourceError::UnresolvedResponse { bytes, .. } => *bytes,
+            _ => 0,
+        }
+    }
+
+    /// Timeouts and connection failures of the outcall to the rpc wrapper, the request can succeed through another one.
+    /// Responses of the upstream api, including the server errors and the responses the replicas disagree on,
+    /// are never counted against the wrapper, otherwise any source could make the shared wrapper unhealthy.
+    /// The web3 errors keep no reject code, their rejects are taken from the attempts, see `Source::fetch`
+    pub fn is_network_error(&self) -> bool {
+        matches!(
+            self,
+            SourceError::HttpCacheError(HttpCacheError::TransientHttpOutcallError(_))
+        )
+    }
+
+    /// Failures of the endpoint itself, counted in the source health.
+    /// Misconfigurations of the owner, e.g. an invalid request, api key or resolver, are not
+    pub fn is_upstream_failure(&self) -> bool {
+        match self {
+            SourceError::HttpCacheError(err) => matches!(
+                err,
+                HttpCacheError::HttpOutcallError(_)
+                    | HttpCacheError::TransientHttpOutcallError(_)
+                    | HttpCacheError::NoConsensus(_)
+                    | HttpCacheError::ServerError(_)
+            ),
+            SourceError::Web3Error(err) => !matches!(
+                err,
+                web3::Web3Error::AddressError(_)
+                    | web3::Web3Error::InvalidAddressFormat(_)
+                    | web3::Web3Error::UnableToFormCallData(_)
+            ),
+            SourceError::FailedToGetLogs(_)
+            | SourceError::CanisterCallFailed(_)
+            | SourceError::BitcoinError(_) => true,
+            _ => false,
+        }
     }
 }
 
 impl Source {
+    /// Checks the source config without fetching it, including the field path of the event logs against the ABI
+    pub fn validate(&self) -> Result<(), SourceError> {
+        match self {
+            Source::HttpSource(http_source) => http_source.validate()?,
+            Source::EvmEventLogsSource(evm_event_logs_source) => {
+                evm_event_logs_source.validate()?;
+                evm_event_logs_source.event_field()?;
+            }
+            Source::EvmContractCallSource(evm_contract_call_source) => {
+                evm_contract_call_source.validate()?
+            }
+            Source::EvmStorageSource(evm_storage_source) => evm_storage_source.validate()?,
+            Source::EvmBalanceSource(evm_balance_source) => evm_balance_source.validate()?,
+            Source::CanisterCallSource(canister_call_source) => canister_call_source.validate()?,
+            Source::BitcoinSource(bitcoin_source) => bitcoin_source.validate()?,
+            Source::Icrc1Source(_) => {}
+        }
+
+        Ok(())
+    }
+
     pub async fn rate(&self, expr_freq: Seconds) -> Result<RateResult, SourceError> {
+        self.fetch(expr_freq, true).await
+    }
+
+    /// Same as `rate`, but neither the state of the indexed sources, the http cache
+    /// nor the health of the rpc wrappers is updated
+    pub async fn preview(&self, expr_freq: Seconds) -> Result<RateResult, SourceError> {
+        self.fetch(expr_freq, false).await
+    }
+
+    /// Fetches the rate through the rpc wrappers, the next wrapper is tried on network errors
+    /// while the attempts of the source are left
+    async fn fetch(&self, expr_freq: Seconds, persist: bool) -> Result<RateResult, SourceError> {
+        let wrappers = if self.uses_rpc_wrapper() {
+            RpcWrappers::candidates()
+        } else {
+            vec![]
+        };
+
+        let attempts = Attempts::new(MAX_SOURCE_ATTEMPTS);
+        if wrappers.is_empty() {
+            return self
+                .rate_with_wrapper(None, expr_freq, persist, &attempts)
+                .await;
+        }
+
+        let mut last_err = None;
+        for wrapper in wrappers {
+            if attempts.is_exhausted() {
+                break;
+            }
+
+            let result = self
+                .rate_with_wrapper(Some(&wrapper), expr_freq, persist, &attempts)
+                .await;
+            let rejects = attempts.take_rejects();
+
+            match result {
+                Err(err)
+                    if err.is_network_error() || rejects.iter().any(Reject::is_network_failure) =>
+                {
+                    if persist {
+                        RpcWrappers::report_failure(&wrapper);
+                    }
+                    last_err = Some(err);
+                }
+                result => {
+                    if persist {
+                        RpcWrappers::report_success(&wrapper);
+                    }
+                    return result;
+                }
+            }
+        }
+
+        Err(last_err.expect("at least one wrapper should be tried"))
+    }
+
+    fn uses_rpc_wrapper(&self) -> bool {
+        let rpc_cfg = match self {
+            Source::HttpSource(http_source) => return http_source.use_rpc_wrapper.unwrap_or(true),
+            Source::EvmEventLogsSource(evm_event_logs_source) => &evm_event_logs_source.rpc_cfg,
+            Source::EvmContractCallSource(evm_contract_call_source) => {
+                &evm_contract_call_source.rpc_cfg
+            }
+            Source::EvmStorageSource(evm_storage_source) => &evm_storage_source.rpc_cfg,
+            Source::EvmBalanceSource(evm_balance_source) => &evm_balance_source.rpc_cfg,
+            Source::CanisterCallSource(_) | Source::Icrc1Source(_) | Source::BitcoinSource(_) => {
+                return false
+            }
+        };
+
+        rpc_cfg
+            .as_ref()
+            .and_then(|rpc_cfg| rpc_cfg.use_rpc_wrapper)
+            .unwrap_or(true)
+    }
+
+    async fn rate_with_wrapper(
+        &self,
+        wrapper: Option<&str>,
+        expr_freq: Seconds,
+        persist: bool,
+        attempts: &Attempts,
+    ) -> Result<RateResult, SourceError> {
         match self {
-            Source::HttpSource(http_source) => Source::http_rate(http_source, expr_freq).await,
+            Source::HttpSource(http_source) => {
+                Source::http_rate(http_source, wrapper, expr_freq, persist, attempts).await
+            }
             Source::EvmEventLogsSource(evm_event_logs_source) => {
-                Source::evm_event_logs_rate(evm_event_logs_source, expr_freq).await
+                Source::evm_event_logs_rate(
+                    evm_event_logs_source,
+                    wrapper,
+                    expr_freq,
+                    persist,
+                    attempts,
+                )
+                .await
+            }
+            Source::EvmContractCallSource(evm_contract_call_source) => {
+                Source::evm_contract_call_rate(
+                    evm_contract_call_source,
+                    wrapper,
+                    expr_freq,
+                    attempts,
+                )
+                .await
+            }
+            Source::EvmStorageSource(evm_storage_source) => {
+                Source::evm_storage_rate(evm_storage_source, wrapper, expr_freq, attempts).await
+            }
+            Source::EvmBalanceSource(evm_balance_source) => {
+                Source::evm_balance_rate(evm_balance_source, wrapper, expr_freq, attempts).await
+            }
+            Source::CanisterCallSource(canister_call_source) => {
+                Source::canister_call_rate(canister_call_source, expr_freq).await
+            }
+            Source::Icrc1Source(icrc1_source) => Source::icrc1_rate(icrc1_source, expr_freq).await,
+            Source::BitcoinSource(bitcoin_source) => {
+                Source::bitcoin_rate(bitcoin_source, expr_freq).await
+            }
+        }
+    }
+
+    /// Web3 instance calling the rpc and the other providers, through the rpc wrapper if it is set
+    fn web3_instance(
+        rpc: &str,
+        rpc_cfg: &Option<RpcCfg>,
+        wrapper: Option<&str>,
+        retry_policy: RetryPolicy,
+        attempts: &Attempts,
+    ) -> Result<web3::Web3Instance<impl Transport>, SourceError> {
+        let rpc_cfg = rpc_cfg.clone().unwrap_or_default();
+        rpc_cfg.validate().map_err(SourceError::InvalidRequest)?;
+
+        let urls = rpc_cfg
+            .urls(rpc)
+            .into_iter()
+            .map(|url| match wrapper {
+                Some(wrapper) => RpcWrappers::wrap(wrapper, &url),
+                None => url,
+            })
+            .collect();
+
+        Ok(web3::instance(
+            urls,
+            &rpc_cfg,
+            clone_with_state!(evm_rpc_canister),
+            retry_policy,
+            attempts.clone(),
+        ))
+    }
+
+    pub async fn evm_storage_rate(
+        evm_storage_source: &EvmStorageSource,
+        wrapper: Option<&str>,
+        _expr_freq: Seconds,
+        attempts: &Attempts,
+    ) -> Result<RateResult, SourceError> {
+        evm_storage_source.validate()?;
+
+        let slot = parse_u256(&evm_storage_source.slot)?;
+        let addresses = evm_storage_source
+            .addresses
+            .iter()
+            .map(|address| address::to_h160(address))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let w3 = Self::web3_instance(
+            &evm_storage_source.rpc,
+            &evm_storage_source.rpc_cfg,
+            wrapper,
+            RetryPolicy::default(),
+            attempts,
+        )?;
+
+        let values = join_all(
+            addresses
+                .into_iter()
+                .map(|address| w3.get_storage_at(address, slot, evm_storage_source.block)),
+        )
+        .await
+        .into_iter()
+        .map(|value| value.map(|value| U256::from_big_endian(value.as_bytes())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RateResult {
+            rate: Value::String(sum_u256(&values)?.to_string()),
+            cached_at: time::in_seconds(),
+            bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    pub async fn evm_balance_rate(
+        evm_balance_source: &EvmBalanceSource,
+        wrapper: Option<&str>,
+        _expr_freq: Seconds,
+        attempts: &Attempts,
+    ) -> Result<RateResult, SourceError> {
+        evm_balance_source.validate()?;
+
+        let token = evm_balance_source
+            .token
+            .as_ref()
+            .map(|token| address::to_h160(token))
+            .transpose()?;
+        let addresses = evm_balance_source
+            .addresses
+            .iter()
+            .map(|address| address::to_h160(address))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let w3 = Self::web3_instance(
+            &evm_balance_source.rpc,
+            &evm_balance_source.rpc_cfg,
+            wrapper,
+            RetryPolicy::default(),
+            attempts,
+        )?;
+        let block = evm_balance_source.block;
+
+        let balances = join_all(addresses.into_iter().map(|address| {
+            let w3 = &w3;
+            async move {
+                match token {
+                    Some(token) => w3.get_erc20_balance(token, address, block).await,
+                    None => w3.get_balance(address, block).await,
+                }
+            }
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RateResult {
+            rate: Value::String(sum_u256(&balances)?.to_string()),
+            cached_at: time::in_seconds(),
+            bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    pub async fn evm_contract_call_rate(
+        evm_contract_call_source: &EvmContractCallSource,
+        wrapper: Option<&str>,
+        _expr_freq: Seconds,
+        attempts: &Attempts,
+    ) -> Result<RateResult, SourceError> {
+        evm_contract_call_source.validate()?;
+
+        let contract = Contract::load(evm_contract_call_source.abi.as_bytes())
+            .map_err(|err| SourceError::FailedToParseABI(err.to_string()))?;
+
+        let function = contract
+            .function(&evm_contract_call_source.function_name)
+            .map_err(|err| SourceError::FailedToParseABI(err.to_string()))?;
+
+        let args = evm_contract_call_source.args.clone().unwrap_or_default();
+        if args.len() != function.inputs.len() {
+            return Err(SourceError::FailedToEncodeCall(format!(
+                "expected {} arguments, got {}",
+                function.inputs.len(),
+                args.len()
+            )));
+        }
+
+        let tokens = function
+            .inputs
+            .iter()
+            .zip(args.iter())
+            .map(|(param, arg)| LenientTokenizer::tokenize(&param.kind, arg))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| SourceError::FailedToEncodeCall(err.to_string()))?;
+
+        let data = function
+            .encode_input(&tokens)
+            .map_err(|err| SourceError::FailedToEncodeCall(err.to_string()))?;
+
+        let w3 = Self::web3_instance(
+            &evm_contract_call_source.rpc,
+            &evm_contract_call_source.rpc_cfg,
+            wrapper,
+            RetryPolicy::default(),
+            attempts,
+        )?;
+
+        let output = w3
+            .call(
+                address::to_h160(&evm_contract_call_source.address)?,
+                data,
+                evm_contract_call_source.block,
+            )
+            .await?;
+
+        let mut outputs = function
+            .decode_output(&output)
+            .map_err(|err| SourceError::FailedToDecodeOutput(err.to_string()))?;
+
+        let output_index = evm_contract_call_source.output_index.unwrap_or_default() as usize;
+        if outputs.len() <= output_index {
+            return Err(SourceError::InvalidRequest(
+                "Output index is out of range".to_string(),
+            ));
+        }
+
+        Ok(RateResult {
+            rate: abi::token_to_value(outputs.swap_remove(output_index)),
+            cached_at: time::in_seconds(),
+            bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    /// Returns the source with the api keys referencing secrets filled with their values
+    pub async fn with_secrets(&self, owner: &Address) -> Result<Self, SourceError> {
+        let mut source = self.clone();
+
+        if let Source::HttpSource(http_source) = &mut source {
+            for api_key in http_source.api_keys.iter_mut().flatten() {
+                if let Some(secret) = &api_key.secret {
+                    api_key.key = Secrets::get(owner, secret).await?;
+                }
             }
         }
+
+        Ok(source)
     }
 
     pub async fn evm_event_logs_rate(
         evm_event_logs_source: &EvmEventLogsSource,
+        wrapper: Option<&str>,
         _expr_freq: Seconds,
+        persist: bool,
+        attempts: &Attempts,
     ) -> Result<RateResult, SourceError> {
         evm_event_logs_source.validate()?;
 
-        let rpc_wrapper = clone_with_state!(rpc_wrapper);
-        let url = format!(
-            "{}{}&cacheTTL={}",
-            rpc_wrapper,
-            urlencoding::encode(&evm_event_logs_source.rpc),
-            ORALLY_WRAPPER_CAHCHE_TTL
-        );
+        let retry_policy = evm_event_logs_source.retry_policy.unwrap_or_default();
+        retry_policy
+            .validate()
+            .map_err(SourceError::InvalidRequest)?;
 
-        let w3 = web3::instance(url, clone_with_state!(evm_rpc_canister));
+        let w3 = Self::web3_instance(
+            &evm_event_logs_source.rpc,
+            &evm_event_logs_source.rpc_cfg,
+            wrapper,
+            retry_policy,
+            attempts,
+        )?;
 
-        let topic = if let Some(topic) = &evm_event_logs_source.topic {
-            Some(
-                H256::from_str(&topic)
-                    .map_err(|err| SourceError::InvalidRequest(err.to_string()))?,
-            )
-        } else {
-            None
-        };
+        let topics = [
+            parse_topic(&evm_event_logs_source.topic)?,
+            parse_topic(&evm_event_logs_source.topic1)?,
+            parse_topic(&evm_event_logs_source.topic2)?,
+            parse_topic(&evm_event_logs_source.topic3)?,
+        ];
 
         let address = if let Some(address) = &evm_event_logs_source.address {
             Some(
@@ -156,23 +987,113 @@ impl Source {
             None
         };
 
-        let mut logs = w3
-            .get_logs(
-                evm_event_logs_source.from_block,
-                evm_event_logs_source.to_block,
-                topic,
+        let (event, field) = evm_event_logs_source.event_field()?;
+
+        if let Some(indexing) = &evm_event_logs_source.indexing {
+            if evm_event_logs_source.from_block.is_some()
+                || evm_event_logs_source.to_block.is_some()
+                || evm_event_logs_source.range.is_some()
+                || block_hash.is_some()
+            {
+                return Err(SourceError::InvalidRequest(
+                    "indexing can't be used with from_block, to_block, block_hash or range"
+                        .to_string(),
+                ));
+            }
+
+            return Self::evm_event_logs_indexed_rate(
+                evm_event_logs_source,
+                indexing,
+                &w3,
+                &event,
+                &field,
+                topics,
                 address,
-                block_hash,
+                persist,
             )
+            .await;
+        }
+
+        let (from_block, to_block) = match &evm_event_logs_source.range {
+            Some(_)
+                if evm_event_logs_source.from_block.is_some()
+                    || evm_event_logs_source.to_block.is_some()
+                    || block_hash.is_some() =>
+            {
+                return Err(SourceError::InvalidRequest(
+                    "range can't be used with from_block, to_block or block_hash".to_string(),
+                ));
+            }
+            Some(BlockRange::LastBlocks(0)) => {
+                return Err(SourceError::InvalidRequest(
+                    "range should contain at least one block".to_string(),
+                ));
+            }
+            Some(range) => {
+                let blocks = match range {
+                    BlockRange::Latest => 1,
+                    BlockRange::LastBlocks(blocks) => *blocks,
+                };
+
+                // pin the range to the block number, so the request is the same for all the replicas
+                // and `from_block` and `to_block` can't resolve to different latest blocks
+                let latest = w3
+                    .get_block_number()
+                    .await
+                    .map_err(|err| SourceError::FailedToGetLogs(err.to_string()))?;
+
+                (
+                    Some(BlockNumber::Number(
+                        latest.saturating_sub(blocks - 1).into(),
+                    )),
+                    Some(BlockNumber::Number(latest.into())),
+                )
+            }
+            None => {
+                if let (Some(from), Some(to)) = (
+                    evm_event_logs_source.from_block,
+                    evm_event_logs_source.to_block,
+                ) {
+                    if from > to {
+                        return Err(SourceError::InvalidRequest(
+                            "from_block is greater than to_block".to_string(),
+                        ));
+                    }
+                }
+
+                (
+                    evm_event_logs_source
+                        .from_block
+                        .map(|block| BlockNumber::Number(block.into())),
+                    evm_event_logs_source
+                        .to_block
+                        .map(|block| BlockNumber::Number(block.into())),
+                )
+            }
+        };
+
+        let mut logs = w3
+            .get_logs(from_block, to_block, topics, address, block_hash)
             .await
             .map_err(|err| SourceError::FailedToGetLogs(err.to_string()))?;
 
-        let contract = Contract::load(evm_event_logs_source.event_abi.as_bytes())
-            .map_err(|err| SourceError::FailedToParseABI(err.to_string()))?;
+        if let Some(aggregation) = &evm_event_logs_source.aggregation {
+            let mut accumulator = LogsAccumulator::default();
+            EvmEventLogsSource::aggregate_logs(
+                aggregation,
+                &event,
+                &field,
+                logs,
+                &mut accumulator,
+            )?;
 
-        let event = contract
-            .event(&evm_event_logs_source.event_name)
-            .map_err(|err| SourceError::FailedToParseABI(err.to_string()))?;
+            return Ok(RateResult {
+                rate: accumulator.result(aggregation)?,
+                cached_at: time::in_seconds(),
+                bytes: 0,
+                excerpt: None,
+            });
+        }
 
         if logs.len() <= evm_event_logs_source.log_index as usize {
             return Err(SourceError::InvalidRequest(
@@ -184,88 +1105,314 @@ impl Source {
         // logs vector will be changed after the next line
         let log_at_index = logs.swap_remove(evm_event_logs_source.log_index as usize);
 
-        let raw_log = RawLog {
-            topics: log_at_index.topics,
-            data: log_at_index.data.0,
+        let token = EvmEventLogsSource::log_field(&event, &field, log_at_index)?;
+
+        Ok(RateResult {
+            rate: abi::token_to_value(token),
+            cached_at: time::in_seconds(),
+            bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    /// Fetches the logs of the new final blocks only and folds them into the aggregate stored in the state
+    async fn evm_event_logs_indexed_rate(
+        evm_event_logs_source: &EvmEventLogsSource,
+        indexing: &LogsIndexing,
+        w3: &web3::Web3Instance<impl Transport>,
+        event: &Event,
+        field: &EventFieldPath,
+        topics: [Option<H256>; 4],
+        address: Option<H160>,
+        persist: bool,
+    ) -> Result<RateResult, SourceError> {
+        indexing.validate()?;
+
+        let Some(aggregation) = &evm_event_logs_source.aggregation else {
+            return Err(SourceError::InvalidRequest(
+                "indexing requires aggregation".to_string(),
+            ));
         };
 
-        let log = event
-            .parse_log(raw_log)
-            .map_err(|err| SourceError::FailedToParseLogs(err.to_string()))?;
+        let key = evm_event_logs_source.index_key(indexing)?;
+        let index = LogsIndexes::get(&key);
+        let next_block = index
+            .as_ref()
+            .map_or(indexing.start_block, |index| index.next_block);
+        let mut accumulator = match &index {
+            Some(index) => index.accumulator()?,
+            None => LogsAccumulator::default(),
+        };
 
-        let token = log
-            .params
-            .into_iter()
-            .find(|p| p.name == evm_event_logs_source.event_log_field_name)
-            .ok_or(SourceError::LogFieldNotFound(
-                evm_event_logs_source.event_log_field_name.clone(),
-            ))?
-            .value;
+        let latest = w3
+            .get_block_number()
+            .await
+            .map_err(|err| SourceError::FailedToGetLogs(err.to_string()))?;
+        let last_final_block = latest.saturating_sub(indexing.confirmations());
+
+        if next_block <= last_final_block {
+            let to_block = last_final_block
+                .min(next_block.saturating_add(indexing.max_blocks_per_fetch() - 1));
+
+            let logs = w3
+                .get_logs(
+                    Some(BlockNumber::Number(next_block.into())),
+                    Some(BlockNumber::Number(to_block.into())),
+                    topics,
+                    address,
+                    None,
+                )
+                .await
+                .map_err(|err| SourceError::FailedToGetLogs(err.to_string()))?;
+
+            EvmEventLogsSource::aggregate_logs(aggregation, event, field, logs, &mut accumulator)?;
+
+            if !persist {
+                return Ok(RateResult {
+                    rate: accumulator.result(aggregation)?,
+                    cached_at: time::in_seconds(),
+                    bytes: 0,
+                    excerpt: None,
+                });
+            }
+
+            let is_stored = LogsIndexes::compare_and_set(
+                &key,
+                index.map(|index| index.next_block),
+                LogsIndex::new(to_block + 1, &accumulator),
+            );
+
+            // a concurrent call has already advanced the index further
+            if !is_stored {
+                if let Some(index) = LogsIndexes::get(&key) {
+                    accumulator = index.accumulator()?;
+                }
+            }
+        }
+
+        Ok(RateResult {
+            rate: accumulator.result(aggregation)?,
+            cached_at: time::in_seconds(),
+            bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    pub async fn canister_call_rate(
+        canister_call_source: &CanisterCallSource,
+        _expr_freq: Seconds,
+    ) -> Result<RateResult, SourceError> {
+        canister_call_source.validate()?;
+        Self::check_canister_call(canister_call_source)?;
+
+        let args = canister_call_source
+            .args
+            .clone()
+            .unwrap_or_else(|| EMPTY_CANDID_ARGS.to_vec());
+
+        let reply = ic_cdk::api::call::call_raw(
+            canister_call_source.canister_id,
+            &canister_call_source.method,
+            args,
+            0,
+        )
+        .await
+        .map_err(|(code, msg)| SourceError::CanisterCallFailed(format!("{code:?}: {msg}")))?;
+
+        let rate = candid_value::resolve_reply(&reply, canister_call_source.path.as_deref())?;
+
+        Ok(RateResult {
+            rate,
+            cached_at: time::in_seconds(),
+            bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    /// Only the read-only methods of the canisters allowed by the controllers are called,
+    /// an arbitrary canister could keep the call open and block the upgrades of sybil
+    fn check_canister_call(canister_call_source: &CanisterCallSource) -> Result<(), SourceError> {
+        let canister_id = canister_call_source.canister_id;
+        if canister_id == ic_cdk::id()
+            || canister_id == Principal::management_canister()
+            || !clone_with_state!(canister_call_canisters).contains(&canister_id)
+        {
+            return Err(SourceError::CanisterCallNotAllowed(format!(
+                "canister {canister_id} is not in the allowed canisters"
+            )));
+        }
+
+        let method = &canister_call_source.method;
+        let is_allowed = match clone_with_state!(canister_call_methods) {
+            Some(methods) => methods.contains(method),
+            None => DEFAULT_CANISTER_CALL_METHODS.contains(&method.as_str()),
+        };
+
+        if !is_allowed {
+            return Err(SourceError::CanisterCallNotAllowed(format!(
+                "method {method} is not in the allowed read-only methods"
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn icrc1_rate(
+        icrc1_source: &Icrc1Source,
+        _expr_freq: Seconds,
+    ) -> Result<RateResult, SourceError> {
+        let ledger = icrc1_source.ledger;
+
+        let amount = match &icrc1_source.metric {
+            Icrc1Metric::TotalSupply => icrc1_call(ledger, "icrc1_total_supply", ()).await?,
+            Icrc1Metric::Fee => icrc1_call(ledger, "icrc1_fee", ()).await?,
+            Icrc1Metric::BalanceOf(accounts) => {
+                if accounts.is_empty() || accounts.len() as u64 > MAX_ICRC1_ACCOUNTS {
+                    return Err(SourceError::InvalidRequest(format!(
+                        "from 1 to {MAX_ICRC1_ACCOUNTS} accounts are allowed"
+                    )));
+                }
+
+                let futures = accounts
+                    .iter()
+                    .map(|account| icrc1_call::<Nat>(ledger, "icrc1_balance_of", (account,)));
 
-        let data = match token {
-            Token::Int(val) | Token::Uint(val) => serde_json::to_value(val.to_string())?,
-            Token::FixedArray(val) | Token::Array(val) | Token::Tuple(val) => {
-                serde_json::to_value(val)?
+                join_all(futures)
+                    .await
+                    .into_iter()
+                    .try_fold(Nat::from(0u8), |sum, balance| {
+                        balance.map(|balance| sum + balance)
+                    })?
             }
-            Token::String(val) => serde_json::to_value(val)?,
-            Token::Bytes(val) | Token::FixedBytes(val) => serde_json::to_value(val)?,
-            Token::Address(val) => serde_json::to_value(val)?,
-            Token::Bool(val) => serde_json::to_value(val)?,
         };
 
+        let decimals: u8 = icrc1_call(ledger, "icrc1_decimals", ()).await?;
+
         Ok(RateResult {
-            rate: serde_json::to_value(&data)?,
-            cached_at: 0,
+            rate: Value::String(nat::to_decimal_string(&amount, decimals as u32)),
+            cached_at: time::in_seconds(),
             bytes: 0,
+            excerpt: None,
+        })
+    }
+
+    pub async fn bitcoin_rate(
+        bitcoin_source: &BitcoinSource,
+        _expr_freq: Seconds,
+    ) -> Result<RateResult, SourceError> {
+        bitcoin_source.validate()?;
+
+        let mode = bitcoin_source.mode.unwrap_or_default();
+        let futures = bitcoin_source.addresses.iter().map(|address| {
+            let address = address.trim().to_string();
+            let (network, min_confirmations) =
+                (bitcoin_source.network, bitcoin_source.min_confirmations);
+
+            async move {
+                match mode {
+                    BitcoinSourceMode::Balance => {
+                        bitcoin::get_balance(network, address, min_confirmations).await
+                    }
+                    BitcoinSourceMode::Utxos => {
+                        bitcoin::get_utxos_value(network, address, min_confirmations).await
+                    }
+                }
+            }
+        });
+
+        let results = join_all(futures)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<(Satoshi, u128)>, _>>()?;
+
+        let total = results
+            .iter()
+            .map(|(value, _)| Nat::from(*value))
+            .fold(Nat::from(0u8), |sum, value| sum + value);
+        let cycles = results.iter().map(|(_, cycles)| cycles).sum::<u128>();
+
+        Ok(RateResult {
+            rate: Value::String(nat::to_decimal_string(&total, BITCOIN_DECIMALS)),
+            cached_at: time::in_seconds(),
+            // the cycles of the Bitcoin API are charged as the outcall bytes of the same cost
+            bytes: cycles.div_ceil(OUTCALL_CYCLES_PER_BYTE) as usize,
+            excerpt: None,
         })
     }
 
     pub async fn http_rate(
         http_source: &HttpSource,
+        wrapper: Option<&str>,
         expr_freq: Seconds,
+        persist: bool,
+        attempts: &Attempts,
     ) -> Result<RateResult, SourceError> {
         http_source.validate()?;
 
-        let rpc_wrapper = clone_with_state!(rpc_wrapper);
+        let method = http_source.get_method();
+        if http_source.body.is_some() && method != HttpMethod::POST {
+            return Err(SourceError::InvalidRequest(
+                "Request body is allowed only for POST requests".to_string(),
+            ));
+        }
+
+        let url = http_source.get_url_with_keys();
         let req = CanisterHttpRequestArgument {
-            url: format!(
-                "{}{}&cacheTTL={}",
-                rpc_wrapper,
-                urlencoding::encode(&http_source.get_url_with_keys()),
-                ORALLY_WRAPPER_CAHCHE_TTL
-            ),
+            url: match wrapper {
+                Some(wrapper) => RpcWrappers::wrap(wrapper, &url),
+                None => url,
+            },
             max_response_bytes: http_source.expected_bytes,
-            headers: Self::get_default_headers(),
+            method,
+            headers: http_source.get_headers_with_keys(),
+            body: http_source.get_body_with_keys(),
             ..Default::default()
         };
 
         defer!(cache_cleaner::execute());
 
+        let retry_policy = http_source.retry_policy.unwrap_or_default();
+        retry_policy
+            .validate()
+            .map_err(SourceError::InvalidRequest)?;
+
+        let req = &req;
         let (response, cached_at) =
-            retry_until_success!(HttpCache::request_with_access(&req, expr_freq))?;
+            retry_with_attempts(&retry_policy, attempts, move || async move {
+                if persist {
+                    HttpCache::request_with_access(req, expr_freq).await
+                } else {
+                    HttpCache::request_without_caching(req).await
+                }
+            })
+            .await?;
         let bytes = response.body.len();
 
-        let ptr = Pointer::try_from(http_source.resolver.clone())
-            .map_err(|err| HttpCacheError::InvalidResponseBodyResolver(format!("{err:?}")))?;
-
-        let data = serde_json::from_slice::<Value>(&response.body)?;
+        let excerpt =
+            String::from_utf8_lossy(&response.body[..response.body.len().min(MAX_EXCERPT_BYTES)])
+                .to_string();
 
-        let rate = data
-            .resolve(&ptr)
-            .map_err(|err| HttpCacheError::InvalidResponseBodyResolver(format!("{err:?}")))?
-            .clone();
+        let rate =
+            http_source
+                .resolve(&response.body)
+                .map_err(|err| SourceError::UnresolvedResponse {
+                    excerpt: excerpt.clone(),
+                    bytes,
+                    error: Box::new(err),
+                })?;
 
         Ok(RateResult {
             rate,
             cached_at,
             bytes,
+            excerpt: Some(excerpt),
         })
     }
 
     pub fn search(&self, search: &str) -> bool {
         match self {
             Source::HttpSource(http_source) => {
-                let uri = http_source.get_url_with_keys().trim().to_lowercase();
+                let uri = http_source.uri.trim().to_lowercase();
                 strsim::jaro(&uri, search) >= 0.65
             }
             Source::EvmEventLogsSource(evm_event_logs_source) => {
@@ -300,6 +1447,55 @@ impl Source {
                     || strsim::jaro(&block_hash, search) >= 0.65
                     || strsim::jaro(&event_log_field_name, search) >= 0.65
             }
+            Source::EvmContractCallSource(evm_contract_call_source) => {
+                let rpc = evm_contract_call_source.rpc.trim().to_lowercase();
+                let address = evm_contract_call_source.address.trim().to_lowercase();
+                let function_name = evm_contract_call_source.function_name.trim().to_lowercase();
+
+                strsim::jaro(&rpc, search) >= 0.65
+                    || strsim::jaro(&address, search) >= 0.65
+                    || strsim::jaro(&function_name, search) >= 0.65
+            }
+            Source::EvmStorageSource(evm_storage_source) => {
+                let rpc = evm_storage_source.rpc.trim().to_lowercase();
+
+                strsim::jaro(&rpc, search) >= 0.65
+                    || evm_storage_source
+                        .addresses
+                        .iter()
+                        .any(|address| strsim::jaro(&address.trim().to_lowercase(), search) >= 0.65)
+            }
+            Source::EvmBalanceSource(evm_balance_source) => {
+                let rpc = evm_balance_source.rpc.trim().to_lowercase();
+                let token = evm_balance_source
+                    .token
+                    .clone()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase();
+
+                strsim::jaro(&rpc, search) >= 0.65
+                    || strsim::jaro(&token, search) >= 0.65
+                    || evm_balance_source
+                        .addresses
+                        .iter()
+                        .any(|address| strsim::jaro(&address.trim().to_lowercase(), search) >= 0.65)
+            }
+            Source::CanisterCallSource(canister_call_source) => {
+                let canister_id = canister_call_source.canister_id.to_text();
+                let method = canister_call_source.method.trim().to_lowercase();
+
+                strsim::jaro(&canister_id, search) >= 0.65 || strsim::jaro(&method, search) >= 0.65
+            }
+            Source::Icrc1Source(icrc1_source) => {
+                let ledger = icrc1_source.ledger.to_text();
+
+                strsim::jaro(&ledger, search) >= 0.65
+            }
+            Source::BitcoinSource(bitcoin_source) => bitcoin_source
+                .addresses
+                .iter()
+                .any(|address| strsim::jaro(&address.trim().to_lowercase(), search) >= 0.65),
         }
     }
 
@@ -316,3 +1512,123 @@ impl Source {
         ]
     }
 }
+
+async fn icrc1_call<T: CandidType + for<'de> Deserialize<'de>>(
+    ledger: Principal,
+    method: &str,
+    args: impl ArgumentEncoder,
+) -> Result<T, SourceError> {
+    ic_cdk::call::<_, (T,)>(ledger, method, args)
+        .await
+        .map(|(result,)| result)
+        .map_err(|(code, msg)| SourceError::CanisterCallFailed(format!("{method} {code:?}: {msg}")))
+}
+
+fn parse_topic(topic: &Option<String>) -> Result<Option<H256>, SourceError> {
+    topic
+        .as_ref()
+        .map(|topic| {
+            H256::from_str(topic).map_err(|err| SourceError::InvalidRequest(err.to_string()))
+        })
+        .transpose()
+}
+
+/// Parses `0x` prefixed hex or decimal uint256
+fn parse_u256(value: &str) -> Result<U256, SourceError> {
+    let value = value.trim();
+    let result = match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).map_err(|err| err.to_string()),
+        None => U256::from_dec_str(value).map_err(|err| err.to_string()),
+    };
+
+    result.map_err(|err| SourceError::InvalidRequest(format!("invalid uint256 {value}: {err}")))
+}
+
+fn sum_u256(values: &[U256]) -> Result<U256, SourceError> {
+    values.iter().try_fold(U256::zero(), |sum, value| {
+        sum.checked_add(*value)
+            .ok_or_else(|| SourceError::InvalidRequest("sum overflows uint256".to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_key() {
+        let indexing = LogsIndexing {
+            start_block: 100,
+            confirmations: None,
+            max_blocks_per_fetch: None,
+        };
+        let source = EvmEventLogsSource {
+            rpc: "https://rpc.example.com".to_string(),
+            address: Some("0xAbC".to_string()),
+            topic: Some("0xDEF".to_string()),
+            event_name: "Transfer".to_string(),
+            event_log_field_name: "value".to_string(),
+            ..Default::default()
+        };
+        let key = source.index_key(&indexing).unwrap();
+
+        // the providers, the retries, the indexing pace and the hex case don't reset the index
+        let same = EvmEventLogsSource {
+            address: Some("0xabc".to_string()),
+            topic: Some("0xdef".to_string()),
+            rpc_cfg: Some(RpcCfg {
+                providers: Some(vec!["https://other.example.com".to_string()]),
+                ..Default::default()
+            }),
+            retry_policy: Some(RetryPolicy {
+                max_retries: Some(1),
+                ..Default::default()
+            }),
+            ..source.clone()
+        };
+        let same_indexing = LogsIndexing {
+            confirmations: Some(20),
+            max_blocks_per_fetch: Some(10),
+            ..indexing.clone()
+        };
+        assert_eq!(same.index_key(&same_indexing).unwrap(), key);
+
+        let other_field = EvmEventLogsSource {
+            event_log_field_name: "from".to_string(),
+            ..source.clone()
+        };
+        assert_ne!(other_field.index_key(&indexing).unwrap(), key);
+
+        let other_start = LogsIndexing {
+            start_block: 200,
+            ..indexing
+        };
+        assert_ne!(source.index_key(&other_start).unwrap(), key);
+    }
+
+    #[test]
+    fn test_validate_event_field() {
+        let source = |field: &str| {
+            Source::EvmEventLogsSource(EvmEventLogsSource {
+                rpc: "https://rpc.example.com".to_string(),
+                event_name: "Transfer".to_string(),
+                event_abi: r#"[{"type":"event","name":"Transfer","anonymous":false,"inputs":[
+                    {"name":"from","type":"address","indexed":true},
+                    {"name":"to","type":"address","indexed":true},
+                    {"name":"value","type":"uint256","indexed":false}]}]"#
+                    .to_string(),
+                event_log_field_name: field.to_string(),
+                ..Default::default()
+            })
+        };
+
+        assert!(source("value").validate().is_ok());
+        assert!(matches!(
+            source("amount").validate(),
+            Err(SourceError::LogFieldNotFound(_))
+        ));
+        // `value` is not a tuple
+        assert!(source("value.amount").validate().is_err());
+        assert!(source("value[").validate().is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use candid::CandidType;
+use ic_web3_rs::signing::keccak256;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    feeds::RateResult,
+    source::{Source, SourceError},
+    Address, Seconds, Timestamp,
+};
+use crate::{utils::time, STATE};
+
+/// Weight of the latest call in the success rate and latency averages
+const EWMA_ALPHA: f64 = 0.2;
+/// Sources with the success rate below it are quarantined
+const QUARANTINE_THRESHOLD: f64 = 0.5;
+/// Calls before a source can be quarantined
+const MIN_CALLS: u64 = 5;
+/// Period after which a quarantined source is probed again
+const QUARANTINE_PERIOD: Seconds = 10 * 60;
+/// Sources not called for this period are dropped from the report
+const STALE_PERIOD: Seconds = 7 * 24 * 60 * 60;
+const MAX_ERROR_LEN: usize = 256;
+
+/// Health of a distinct source across the feeds of an owner
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SourceHealth {
+    /// Kind and endpoint of the source
+    pub source: String,
+    pub successes: u64,
+    pub failures: u64,
+    /// Exponentially weighted success rate, from 0 to 1
+    pub score: f64,
+    /// Exponentially weighted latency of the calls
+    pub latency_ms: u64,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<Timestamp>,
+    pub last_checked_at: Timestamp,
+    /// The source isn't called until this time, then it's probed with the next update
+    pub quarantined_until: Option<Timestamp>,
+}
+
+impl SourceHealth {
+    fn new(source: String) -> Self {
+        Self {
+            source,
+            successes: 0,
+            failures: 0,
+            score: 1.0,
+            latency_ms: 0,
+            last_error: None,
+            last_error_at: None,
+            last_checked_at: 0,
+            quarantined_until: None,
+        }
+    }
+
+    pub fn is_quarantined(&self, now: Timestamp) -> bool {
+        self.quarantined_until.is_some_and(|until| until > now)
+    }
+
+    fn record_latency(&mut self, now: Timestamp, latency_ms: u64) {
+        self.latency_ms = if self.successes + self.failures == 0 {
+            latency_ms
+        } else {
+            (EWMA_ALPHA * latency_ms as f64 + (1.0 - EWMA_ALPHA) * self.latency_ms as f64) as u64
+        };
+        self.last_checked_at = now;
+    }
+
+    fn record_success(&mut self, now: Timestamp, latency_ms: u64) {
+        self.record_latency(now, latency_ms);
+        self.successes += 1;
+
+        if self.quarantined_until.take().is_some() {
+            // the probe has succeeded, the source starts over
+            self.score = 1.0;
+        } else {
+            self.score = EWMA_ALPHA + (1.0 - EWMA_ALPHA) * self.score;
+        }
+    }
+
+    fn record_failure(&mut self, now: Timestamp, latency_ms: u64, err: &SourceError) {
+        self.record_latency(now, latency_ms);
+        self.failures += 1;
+        self.score *= 1.0 - EWMA_ALPHA;
+
+        self.last_error = Some(err.to_string().chars().take(MAX_ERROR_LEN).collect());
+        self.last_error_at = Some(now);
+
+        // a failed probe keeps the source in the quarantine
+        if self.quarantined_until.is_some()
+            || (self.successes + self.failures >= MIN_CALLS && self.score < QUARANTINE_THRESHOLD)
+        {
+            self.quarantined_until = Some(now + QUARANTINE_PERIOD);
+        }
+    }
+}
+
+/// Health of the sources keyed by the hash of the owner and the source, see `SourceHealth`
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct SourcesHealth(HashMap<String, SourceHealth>);
+
+impl SourcesHealth {
+    /// The source is hashed as configured in the feed, before the secrets are filled.
+    /// The same source of different owners can use different secrets, so the owner is a part of the key
+    pub fn key(owner: &Address, source: &Source) -> String {
+        let mut data = owner.as_bytes().to_vec();
+        data.extend(serde_json::to_vec(source).expect("source should be serializable"));
+
+        hex::encode(keccak256(&data))
+    }
+
+    pub fn is_quarantined(key: &str) -> bool {
+        let now = time::in_seconds();
+
+        STATE.with(|state| {
+            state
+                .borrow()
+                .sources_health
+                .0
+                .get(key)
+                .is_some_and(|health| health.is_quarantined(now))
+        })
+    }
+
+    pub fn record(
+        key: &str,
+        source: &Source,
+        latency_ms: u64,
+        result: &Result<RateResult, SourceError>,
+    ) {
+        if matches!(result, Err(err) if !err.is_upstream_failure()) {
+            return;
+        }
+
+        let now = time::in_seconds();
+
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let sources_health = &mut state.sources_health.0;
+
+            sources_health
+                .retain(|_, health| now.saturating_sub(health.last_checked_at) < STALE_PERIOD);
+
+            let health = sources_health
+                .entry(key.to_string())
+                .or_insert_with(|| SourceHealth::new(Self::describe(source)));
+
+            match result {
+                Ok(_) => health.record_success(now, latency_ms),
+                Err(err) => health.record_failure(now, latency_ms, err),
+            }
+        })
+    }
+
+    /// Sources ordered from the least healthy one
+    pub fn get_all() -> Vec<SourceHealth> {
+        STATE.with(|state| {
+            let mut report = state
+                .borrow()
+                .sources_health
+                .0
+                .values()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            report.sort_by(|l, r| l.score.total_cmp(&r.score));
+            report
+        })
+    }
+
+    pub fn clear() {
+        STATE.with(|state| {
+            state.borrow_mut().sources_health.0.clear();
+        })
+    }
+
+    fn describe(source: &Source) -> String {
+        match source {
+            Source::HttpSource(source) => format!("HttpSource {}", source.uri),
+            Source::EvmEventLogsSource(source) => format!("EvmEventLogsSource {}", source.rpc),
+            Source::EvmContractCallSource(source) => {
+                format!("EvmContractCallSource {}", source.rpc)
+            }
+            Source::EvmStorageSource(source) => format!("EvmStorageSource {}", source.rpc),
+            Source::EvmBalanceSource(source) => format!("EvmBalanceSource {}", source.rpc),
+            Source::CanisterCallSource(source) => format!(
+                "CanisterCallSource {}.{}",
+                source.canister_id, source.method
+            ),
+            Source::Icrc1Source(source) => format!("Icrc1Source {}", source.ledger),
+            Source::BitcoinSource(source) => format!("BitcoinSource {:?}", source.network),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{cache::HttpCacheError, source::HttpSource};
+
+    fn err() -> SourceError {
+        SourceError::HttpCacheError(HttpCacheError::ServerError("unavailable".to_string()))
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let mut health = SourceHealth::new("HttpSource https://example.com".to_string());
+
+        for now in 0..MIN_CALLS {
+            assert!(!health.is_quarantined(now));
+            health.record_failure(now, 100, &err());
+        }
+
+        assert!(health.is_quarantined(MIN_CALLS));
+        assert!(!health.is_quarantined(MIN_CALLS + QUARANTINE_PERIOD));
+        assert_eq!(
+            health.last_error.as_deref(),
+            Some("HttpCache error: Got error from server: unavailable")
+        );
+
+        // a failed probe extends the quarantine
+        let now = MIN_CALLS + QUARANTINE_PERIOD;
+        health.record_failure(now, 100, &err());
+        assert!(health.is_quarantined(now + 1));
+
+        // a successful probe lifts it
+        let now = now + QUARANTINE_PERIOD;
+        health.record_success(now, 100);
+        assert!(!health.is_quarantined(now));
+        assert_eq!(health.score, 1.0);
+    }
+
+    #[test]
+    fn test_flaky_source_is_not_quarantined() {
+        let mut health = SourceHealth::new("HttpSource https://example.com".to_string());
+
+        for now in 0..20 {
+            if now % 3 == 0 {
+                health.record_failure(now, 100, &err());
+            } else {
+                health.record_success(now, 100);
+            }
+        }
+
+        assert!(!health.is_quarantined(20));
+        assert!(health.score > QUARANTINE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_key() {
+        let source = Source::HttpSource(HttpSource {
+            uri: "https://example.com/price?apikey={key}".to_string(),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            SourcesHealth::key(&"0x01".to_string(), &source),
+            SourcesHealth::key(&"0x01".to_string(), &source)
+        );
+        assert_ne!(
+            SourcesHealth::key(&"0x01".to_string(), &source),
+            SourcesHealth::key(&"0x02".to_string(), &source)
+        );
+    }
+
+    #[test]
+    fn test_upstream_failures() {
+        assert!(err().is_upstream_failure());
+        assert!(
+            SourceError::HttpCacheError(HttpCacheError::TransientHttpOutcallError(
+                "timeout".to_string()
+            ))
+            .is_upstream_failure()
+        );
+        assert!(!SourceError::InvalidRequest("invalid".to_string()).is_upstream_failure());
+        assert!(!SourceError::HttpCacheError(HttpCacheError::ClientError(
+            "unauthorized".to_string()
+        ))
+        .is_upstream_failure());
+        assert!(!SourceError::UnresolvedResponse {
+            excerpt: "{}".to_string(),
+            bytes: 2,
+            error: Box::new(SourceError::InvalidRequest("no value".to_string())),
+        }
+        .is_upstream_failure());
+        assert!(!SourceError::Quarantined.is_upstream_failure());
+
+        // the replicas disagreement is the failure of the source, not of the rpc wrapper
+        let no_consensus =
+            SourceError::HttpCacheError(HttpCacheError::NoConsensus("no consensus".to_string()));
+        assert!(no_consensus.is_upstream_failure());
+        assert!(!no_consensus.is_network_error());
+    }
+}
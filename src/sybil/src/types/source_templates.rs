@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use candid::CandidType;
+use ic_cdk::api::management_canister::http_request::HttpHeader;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::{Validate, ValidationErrors};
+
+use super::source::{ApiKey, HttpSource};
+use crate::STATE;
+
+const MAX_TEMPLATE_NAME_LEN: usize = 64;
+const MAX_TEMPLATE_PARAMETERS: usize = 10;
+/// Value of the parameters the template is validated with
+const SAMPLE_VALUE: &str = "sample";
+/// Characters which would change the meaning of the resolver, e.g. add a pipe stage
+const RESOLVER_RESERVED_CHARS: [char; 3] = ['|', '"', '\''];
+
+#[derive(Error, Debug)]
+pub enum SourceTemplateError {
+    #[error("Source template not found: {0}")]
+    TemplateNotFound(String),
+    #[error("Invalid template name, only alphanumeric characters, '_' and '-' are allowed")]
+    InvalidName,
+    #[error("Invalid template parameters: {0}")]
+    InvalidParameters(String),
+    #[error("Missing template parameter: {0}")]
+    MissingParameter(String),
+    #[error("Unknown template parameter: {0}")]
+    UnknownParameter(String),
+    #[error("Validation error: {0}")]
+    ValidationError(#[from] ValidationErrors),
+}
+
+/// HTTP source with `{parameter}` placeholders in the uri, resolver, body and header values,
+/// e.g. `https://api.binance.com/api/v3/ticker/price?symbol={symbol}`
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct SourceTemplate {
+    pub name: String,
+    pub description: Option<String>,
+    pub source: HttpSource,
+    pub parameters: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct TemplateParameter {
+    pub name: String,
+    pub value: String,
+}
+
+/// Reference to a catalog template, expanded into an `HttpSource` when the feed is created
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct TemplateSource {
+    pub template: String,
+    pub parameters: Vec<TemplateParameter>,
+    /// Keys of the caller, the template itself has none
+    pub api_keys: Option<Vec<ApiKey>>,
+}
+
+impl SourceTemplate {
+    pub fn validate(&self) -> Result<(), SourceTemplateError> {
+        let is_valid_name = |name: &str| {
+            !name.is_empty()
+                && name.len() <= MAX_TEMPLATE_NAME_LEN
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        };
+
+        if !is_valid_name(&self.name) {
+            return Err(SourceTemplateError::InvalidName);
+        }
+
+        if self.parameters.len() > MAX_TEMPLATE_PARAMETERS {
+            return Err(SourceTemplateError::InvalidParameters(format!(
+                "at most {MAX_TEMPLATE_PARAMETERS} parameters are allowed"
+            )));
+        }
+
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if !is_valid_name(parameter) {
+                return Err(SourceTemplateError::InvalidParameters(format!(
+                    "invalid parameter name: {parameter}"
+                )));
+            }
+
+            if self.parameters[..i].contains(parameter) {
+                return Err(SourceTemplateError::InvalidParameters(format!(
+                    "duplicated parameter: {parameter}"
+                )));
+            }
+        }
+
+        if self.source.api_keys.is_some() {
+            return Err(SourceTemplateError::InvalidParameters(
+                "api keys are provided by the template users".to_string(),
+            ));
+        }
+
+        // the placeholders themselves aren't valid in every part of the source
+        let values = self
+            .parameters
+            .iter()
+            .map(|name| (name, SAMPLE_VALUE))
+            .collect::<Vec<_>>();
+
+        Ok(self.fill(&values, None)?.validate()?)
+    }
+
+    /// Fills the placeholders with the parameters, all the template parameters are required
+    pub fn expand(
+        &self,
+        template_source: &TemplateSource,
+    ) -> Result<HttpSource, SourceTemplateError> {
+        for parameter in &template_source.parameters {
+            if !self.parameters.contains(&parameter.name) {
+                return Err(SourceTemplateError::UnknownParameter(
+                    parameter.name.clone(),
+                ));
+            }
+        }
+
+        let mut values = Vec::with_capacity(self.parameters.len());
+        for name in &self.parameters {
+            let value = template_source
+                .parameters
+                .iter()
+                .find(|parameter| parameter.name == *name)
+                .ok_or_else(|| SourceTemplateError::MissingParameter(name.clone()))?;
+
+            values.push((name, value.value.as_str()));
+        }
+
+        let source = self.fill(&values, template_source.api_keys.clone())?;
+        source.validate()?;
+
+        Ok(source)
+    }
+
+    /// Replaces the placeholders with the values escaped for the part of the source they are in:
+    /// url encoded in the uri and JSON escaped in the body
+    fn fill(
+        &self,
+        values: &[(&String, &str)],
+        api_keys: Option<Vec<ApiKey>>,
+    ) -> Result<HttpSource, SourceTemplateError> {
+        for (name, value) in values {
+            if self.source.resolver.contains(&placeholder(name))
+                && value.contains(RESOLVER_RESERVED_CHARS)
+            {
+                return Err(SourceTemplateError::InvalidParameters(format!(
+                    "{name} is used in the resolver and can't contain '|' or quotes"
+                )));
+            }
+        }
+
+        Ok(HttpSource {
+            uri: fill(&self.source.uri, values, |value| {
+                urlencoding::encode(value).into_owned()
+            }),
+            api_keys,
+            resolver: fill(&self.source.resolver, values, str::to_string),
+            body: self
+                .source
+                .body
+                .as_ref()
+                .map(|body| fill(body, values, json_escape)),
+            headers: self.source.headers.as_ref().map(|headers| {
+                headers
+                    .iter()
+                    .map(|header| HttpHeader {
+                        name: header.name.clone(),
+                        value: fill(&header.value, values, str::to_string),
+                    })
+                    .collect()
+            }),
+            ..self.source.clone()
+        })
+    }
+}
+
+fn placeholder(name: &str) -> String {
+    format!("{{{name}}}")
+}
+
+fn fill(text: &str, values: &[(&String, &str)], escape: impl Fn(&str) -> String) -> String {
+    values.iter().fold(text.to_string(), |text, (name, value)| {
+        text.replace(&placeholder(name), &escape(value))
+    })
+}
+
+/// Escapes the value to be put inside a JSON string
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Admin-managed catalog of the source templates, keyed by the template name
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct SourceTemplates(HashMap<String, SourceTemplate>);
+
+impl SourceTemplates {
+    /// Adds the template or replaces the one with the same name
+    pub fn set(template: SourceTemplate) -> Result<(), SourceTemplateError> {
+        template.validate()?;
+
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state
+                .source_templates
+                .0
+                .insert(template.name.clone(), template);
+        });
+
+        Ok(())
+    }
+
+    pub fn remove(name: &str) -> Result<(), SourceTemplateError> {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state
+                .source_templates
+                .0
+                .remove(name)
+                .map(|_| ())
+                .ok_or_else(|| SourceTemplateError::TemplateNotFound(name.to_string()))
+        })
+    }
+
+    pub fn get(name: &str) -> Option<SourceTemplate> {
+        STATE.with(|state| state.borrow().source_templates.0.get(name).cloned())
+    }
+
+    pub fn get_all() -> Vec<SourceTemplate> {
+        STATE.with(|state| {
+            let state = state.borrow();
+            let mut templates = state
+                .source_templates
+                .0
+                .values()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            templates.sort_by(|l, r| l.name.cmp(&r.name));
+            templates
+        })
+    }
+
+    pub fn expand(template_source: &TemplateSource) -> Result<HttpSource, SourceTemplateError> {
+        Self::get(&template_source.template)
+            .ok_or_else(|| SourceTemplateError::TemplateNotFound(template_source.template.clone()))?
+            .expand(template_source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_cdk::api::management_canister::http_request::HttpMethod;
+
+    use super::*;
+
+    fn template() -> SourceTemplate {
+        SourceTemplate {
+            name: "binance".to_string(),
+            source: HttpSource {
+                uri: "https://api.binance.com/api/v3/ticker/price?symbol={symbol}".to_string(),
+                resolver: "/price".to_string(),
+                ..Default::default()
+            },
+            parameters: vec!["symbol".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn template_source(parameters: &[(&str, &str)]) -> TemplateSource {
+        TemplateSource {
+            template: "binance".to_string(),
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| TemplateParameter {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let source = template()
+            .expand(&template_source(&[("symbol", "ETHUSDT")]))
+            .unwrap();
+
+        assert_eq!(
+            source.uri,
+            "https://api.binance.com/api/v3/ticker/price?symbol=ETHUSDT"
+        );
+        assert_eq!(source.resolver, "/price");
+    }
+
+    #[test]
+    fn test_expand_encodes_uri_parameters() {
+        let source = template()
+            .expand(&template_source(&[("symbol", "ETH&x=1")]))
+            .unwrap();
+
+        assert_eq!(
+            source.uri,
+            "https://api.binance.com/api/v3/ticker/price?symbol=ETH%26x%3D1"
+        );
+    }
+
+    #[test]
+    fn test_expand_escapes_body_parameters() {
+        let mut template = template();
+        template.source.method = Some(HttpMethod::POST);
+        template.source.body = Some(r#"{"symbol": "{symbol}"}"#.to_string());
+
+        let source = template
+            .expand(&template_source(&[("symbol", r#"ETH", "limit": "1"#)]))
+            .unwrap();
+
+        assert_eq!(
+            source.body.as_deref(),
+            Some(r#"{"symbol": "ETH\", \"limit\": \"1"}"#)
+        );
+    }
+
+    #[test]
+    fn test_expand_rejects_resolver_injection() {
+        let mut template = template();
+        template.source.resolver = "/{symbol}".to_string();
+
+        assert!(template
+            .expand(&template_source(&[("symbol", "price")]))
+            .is_ok());
+        for value in ["price | add 1", "price\"", "price'"] {
+            assert!(matches!(
+                template.expand(&template_source(&[("symbol", value)])),
+                Err(SourceTemplateError::InvalidParameters(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_expand_invalid_parameters() {
+        assert!(matches!(
+            template().expand(&template_source(&[])),
+            Err(SourceTemplateError::MissingParameter(_))
+        ));
+        assert!(matches!(
+            template().expand(&template_source(&[("symbol", "ETHUSDT"), ("limit", "1")])),
+            Err(SourceTemplateError::UnknownParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(template().validate().is_ok());
+
+        let mut invalid = template();
+        invalid.parameters.push("symbol".to_string());
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = template();
+        invalid.name = "binance price".to_string();
+        assert!(invalid.validate().is_err());
+    }
+}
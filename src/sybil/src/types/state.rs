@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use candid::CandidType;
 use candid::Principal;
@@ -7,6 +7,13 @@ use serde::{Deserialize, Serialize};
 use super::{
     config::{Cfg, UpdateCfg},
     feeds::FeedStorage,
+    key_rotation::{PendingKey, RetiredKey},
+    logs_index::LogsIndexes,
+    rpc::RpcTransport,
+    rpc_wrapper::RpcWrappers,
+    secrets::Secrets,
+    source_health::SourcesHealth,
+    source_templates::SourceTemplates,
     whitelist::Whitelist,
     Address,
 };
@@ -27,7 +34,20 @@ pub struct State {
     pub balances: Balances,
     pub balances_cfg: BalancesCfg,
     pub eth_address: Option<Address>,
+    pub signer_addresses: HashMap<String, Address>,
+    pub signer_public_keys: HashMap<String, String>,
+    pub next_key: Option<PendingKey>,
+    pub previous_key: Option<RetiredKey>,
     pub whitelist: Whitelist,
+    pub secrets: Secrets,
+    pub logs_indexes: LogsIndexes,
+    pub canister_call_canisters: Vec<Principal>,
+    pub canister_call_methods: Option<Vec<String>>,
+    pub bitcoin_canister: Option<Principal>,
+    pub rpc_transport: Option<RpcTransport>,
+    pub rpc_wrappers: RpcWrappers,
+    pub source_templates: SourceTemplates,
+    pub sources_health: SourcesHealth,
 }
 
 impl Default for State {
@@ -43,7 +63,20 @@ impl Default for State {
             balances: Balances::default(),
             balances_cfg: BalancesCfg::default(),
             eth_address: None,
+            signer_addresses: HashMap::new(),
+            signer_public_keys: HashMap::new(),
+            next_key: None,
+            previous_key: None,
             whitelist: Whitelist::default(),
+            secrets: Secrets::default(),
+            logs_indexes: LogsIndexes::default(),
+            canister_call_canisters: vec![],
+            canister_call_methods: None,
+            bitcoin_canister: None,
+            rpc_transport: None,
+            rpc_wrappers: RpcWrappers::default(),
+            source_templates: SourceTemplates::default(),
+            sources_health: SourcesHealth::default(),
         }
     }
 }
@@ -55,9 +88,15 @@ pub fn init(cfg: &Cfg) {
         state.fallback_xrc = cfg.fallback_xrc;
         state.evm_rpc_canister = cfg.evm_rpc_canister;
         state.rpc_wrapper = cfg.rpc_wrapper.clone();
+        state.rpc_wrappers.fallbacks = cfg.rpc_wrapper_fallbacks.clone().unwrap_or_default();
+        state.rpc_wrappers.cache_ttl = cfg.rpc_wrapper_cache_ttl;
         state.key_name = cfg.key_name.clone();
         state.balances_cfg = cfg.balances_cfg.clone();
         state.mock = cfg.mock;
+        state.canister_call_canisters = cfg.canister_call_canisters.clone().unwrap_or_default();
+        state.canister_call_methods = cfg.canister_call_methods.clone();
+        state.bitcoin_canister = cfg.bitcoin_canister;
+        state.rpc_transport = cfg.rpc_transport;
     });
 }
 
@@ -76,6 +115,15 @@ pub fn update(cfg: &UpdateCfg) {
         if let Some(rpc_wrapper) = &cfg.rpc_wrapper {
             state.rpc_wrapper = rpc_wrapper.clone();
         }
+        if let Some(rpc_wrapper_fallbacks) = &cfg.rpc_wrapper_fallbacks {
+            let main = state.rpc_wrapper.clone();
+            state
+                .rpc_wrappers
+                .set_fallbacks(&main, rpc_wrapper_fallbacks.clone());
+        }
+        if let Some(rpc_wrapper_cache_ttl) = &cfg.rpc_wrapper_cache_ttl {
+            state.rpc_wrappers.cache_ttl = Some(*rpc_wrapper_cache_ttl);
+        }
         if let Some(mock) = &cfg.mock {
             state.mock = *mock;
         }
@@ -85,6 +133,18 @@ pub fn update(cfg: &UpdateCfg) {
         if let Some(balances_cfg) = &cfg.balances_cfg {
             state.balances_cfg = balances_cfg.clone();
         }
+        if let Some(canister_call_canisters) = &cfg.canister_call_canisters {
+            state.canister_call_canisters = canister_call_canisters.clone();
+        }
+        if let Some(canister_call_methods) = &cfg.canister_call_methods {
+            state.canister_call_methods = Some(canister_call_methods.clone());
+        }
+        if let Some(bitcoin_canister) = &cfg.bitcoin_canister {
+            state.bitcoin_canister = Some(*bitcoin_canister);
+        }
+        if let Some(rpc_transport) = &cfg.rpc_transport {
+            state.rpc_transport = Some(*rpc_transport);
+        }
     });
 }
 
@@ -96,9 +156,15 @@ pub fn get_cfg() -> Cfg {
             fallback_xrc: state.fallback_xrc,
             evm_rpc_canister: state.evm_rpc_canister,
             rpc_wrapper: state.rpc_wrapper.clone(),
+            rpc_wrapper_fallbacks: Some(state.rpc_wrappers.fallbacks.clone()),
+            rpc_wrapper_cache_ttl: state.rpc_wrappers.cache_ttl,
             mock: state.mock,
             key_name: state.key_name.clone(),
             balances_cfg: state.balances_cfg.clone(),
+            canister_call_canisters: Some(state.canister_call_canisters.clone()),
+            canister_call_methods: state.canister_call_methods.clone(),
+            bitcoin_canister: state.bitcoin_canister,
+            rpc_transport: state.rpc_transport,
         }
     })
 }
@@ -107,4 +173,7 @@ pub fn clear() {
     FeedStorage::clear();
     Balances::clear();
     Whitelist::clear();
+    Secrets::clear();
+    LogsIndexes::clear();
+    SourcesHealth::clear();
 }
@@ -0,0 +1,300 @@
+use ic_web3_rs::{ethabi::Token, types::U256};
+use num_bigint::{BigInt, Sign};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::{
+    address,
+    field_path::{self, InvalidFieldPath, PathSegment},
+};
+
+#[derive(Error, Debug)]
+pub enum AbiError {
+    #[error("{0}")]
+    InvalidPath(#[from] InvalidFieldPath),
+    #[error("Field not found: {0}")]
+    FieldNotFound(String),
+    #[error("Invalid ABI: {0}")]
+    InvalidAbi(String),
+}
+
+/// Converts a decoded ABI token into a JSON value:
+/// integers are converted to decimal strings, bytes to `0x` prefixed hex strings,
+/// addresses to checksummed strings, tuples and arrays to arrays of the converted values
+pub fn token_to_value(token: Token) -> Value {
+    match token {
+        Token::Int(_) | Token::Uint(_) => Value::String(
+            token_to_bigint(&token)
+                .expect("should be an integer")
+                .to_string(),
+        ),
+        Token::FixedArray(val) | Token::Array(val) | Token::Tuple(val) => {
+            Value::Array(val.into_iter().map(token_to_value).collect())
+        }
+        Token::String(val) => Value::String(val),
+        Token::Bytes(val) | Token::FixedBytes(val) => {
+            Value::String(format!("0x{}", hex::encode(val)))
+        }
+        Token::Address(val) => {
+            Value::String(address::from_h160(&val).expect("should be a valid address"))
+        }
+        Token::Bool(val) => Value::Bool(val),
+    }
+}
+
+/// Path to a field of a decoded event parameter, e.g. `order.amounts[1]`.
+/// Tuple components can be selected by the name from the ABI or by the index (`order.0`),
+/// array elements are selected by the index in brackets
+#[derive(Debug)]
+pub struct EventFieldPath {
+    path: String,
+    param: String,
+    segments: Vec<PathSegment>,
+    /// JSON ABI of the top-level parameter, used to find the tuple components by name
+    param_abi: Option<Value>,
+}
+
+impl EventFieldPath {
+    pub fn new(path: &str, abi: &str, event_name: &str) -> Result<Self, AbiError> {
+        let mut segments = field_path::parse(path)?.into_iter();
+
+        let Some(PathSegment::Name(param)) = segments.next() else {
+            return Err(InvalidFieldPath(path.to_string()).into());
+        };
+        let segments = segments.collect::<Vec<_>>();
+
+        let has_named_components = segments
+            .iter()
+            .any(|segment| matches!(segment, PathSegment::Name(_)));
+
+        let param_abi = if has_named_components {
+            let param_abi = find_event_param_abi(abi, event_name, &param)?;
+            check_components(path, &param_abi, &segments)?;
+            Some(param_abi)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            param,
+            segments,
+            param_abi,
+        })
+    }
+
+    /// Name of the top-level event parameter
+    pub fn param(&self) -> &str {
+        &self.param
+    }
+
+    /// Selects the field from the decoded value of the top-level parameter
+    pub fn resolve(&self, token: Token) -> Result<Token, AbiError> {
+        let not_found = || AbiError::FieldNotFound(self.path.clone());
+
+        let mut token = token;
+        let mut abi = self.param_abi.as_ref();
+
+        for segment in &self.segments {
+            token = match (token, segment) {
+                (Token::Tuple(mut components), PathSegment::Index(index)) => {
+                    abi = abi.and_then(|abi| abi["components"].get(*index));
+
+                    if *index >= components.len() {
+                        return Err(not_found());
+                    }
+                    components.swap_remove(*index)
+                }
+                (Token::Tuple(mut components), PathSegment::Name(name)) => {
+                    let index = abi
+                        .and_then(|abi| abi["components"].as_array())
+                        .and_then(|abi_components| {
+                            abi_components
+                                .iter()
+                                .position(|component| component["name"] == name.as_str())
+                        })
+                        .ok_or_else(not_found)?;
+                    abi = abi.and_then(|abi| abi["components"].get(index));
+
+                    if index >= components.len() {
+                        return Err(not_found());
+                    }
+                    components.swap_remove(index)
+                }
+                // the components of a tuple array describe its elements
+                (
+                    Token::Array(mut elements) | Token::FixedArray(mut elements),
+                    PathSegment::Index(index),
+                ) => {
+                    if *index >= elements.len() {
+                        return Err(not_found());
+                    }
+                    elements.swap_remove(*index)
+                }
+                _ => return Err(not_found()),
+            };
+        }
+
+        Ok(token)
+    }
+}
+
+/// Checks that the segments of the path select the existing components of the parameter,
+/// so the invalid paths are rejected before any log is fetched
+fn check_components(
+    path: &str,
+    param_abi: &Value,
+    segments: &[PathSegment],
+) -> Result<(), AbiError> {
+    let not_found = || AbiError::FieldNotFound(path.to_string());
+
+    let mut abi = param_abi;
+    let mut kind = abi["type"].as_str().unwrap_or_default();
+
+    for segment in segments {
+        // the components of a tuple array describe its elements
+        if kind.ends_with(']') {
+            let PathSegment::Index(_) = segment else {
+                return Err(not_found());
+            };
+
+            kind = &kind[..kind.rfind('[').ok_or_else(not_found)?];
+            continue;
+        }
+
+        let components = abi["components"]
+            .as_array()
+            .filter(|_| kind == "tuple")
+            .ok_or_else(not_found)?;
+
+        let index = match segment {
+            PathSegment::Index(index) => *index,
+            PathSegment::Name(name) => components
+                .iter()
+                .position(|component| component["name"] == name.as_str())
+                .ok_or_else(not_found)?,
+        };
+
+        abi = components.get(index).ok_or_else(not_found)?;
+        kind = abi["type"].as_str().unwrap_or_default();
+    }
+
+    Ok(())
+}
+
+fn find_event_param_abi(abi: &str, event_name: &str, param: &str) -> Result<Value, AbiError> {
+    let abi: Value =
+        serde_json::from_str(abi).map_err(|err| AbiError::InvalidAbi(err.to_string()))?;
+
+    let items = match abi {
+        Value::Array(items) => items,
+        item => vec![item],
+    };
+
+    items
+        .into_iter()
+        .filter(|item| item["type"] == "event" && item["name"] == event_name)
+        .find_map(|item| {
+            item["inputs"]
+                .as_array()?
+                .iter()
+                .find(|input| input["name"] == param)
+                .cloned()
+        })
+        .ok_or_else(|| AbiError::FieldNotFound(param.to_string()))
+}
+
+/// Converts an integer token into a big integer, `int` values are decoded from two's complement
+pub fn token_to_bigint(token: &Token) -> Option<BigInt> {
+    match token {
+        Token::Uint(val) => Some(u256_to_bigint(val)),
+        Token::Int(val) if val.bit(255) => Some(u256_to_bigint(val) - (BigInt::from(1u8) << 256)),
+        Token::Int(val) => Some(u256_to_bigint(val)),
+        _ => None,
+    }
+}
+
+fn u256_to_bigint(val: &U256) -> BigInt {
+    let mut buf = [0u8; 32];
+    val.to_big_endian(&mut buf);
+    BigInt::from_bytes_be(Sign::Plus, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ic_web3_rs::types::H160;
+
+    use super::*;
+
+    const ABI: &str = r#"[{
+        "type": "event",
+        "name": "OrderFilled",
+        "anonymous": false,
+        "inputs": [
+            {"name": "maker", "type": "address", "indexed": true},
+            {"name": "order", "type": "tuple", "indexed": false, "components": [
+                {"name": "id", "type": "bytes32"},
+                {"name": "amounts", "type": "uint256[]"},
+                {"name": "delta", "type": "int256"}
+            ]}
+        ]
+    }]"#;
+
+    fn order() -> Token {
+        Token::Tuple(vec![
+            Token::FixedBytes(vec![0xab; 32]),
+            Token::Array(vec![
+                Token::Uint(U256::from(10)),
+                Token::Uint(U256::from(20)),
+            ]),
+            Token::Int(U256::MAX),
+        ])
+    }
+
+    fn resolve(path: &str) -> Result<Value, AbiError> {
+        let path = EventFieldPath::new(path, ABI, "OrderFilled")?;
+        assert_eq!(path.param(), "order");
+
+        path.resolve(order()).map(token_to_value)
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("order.amounts[1]").unwrap(), Value::from("20"));
+        assert_eq!(resolve("order.1[0]").unwrap(), Value::from("10"));
+        assert_eq!(resolve("order.delta").unwrap(), Value::from("-1"));
+        assert_eq!(
+            resolve("order.amounts").unwrap(),
+            Value::from(vec!["10", "20"])
+        );
+        assert!(resolve("order.amounts[2]").is_err());
+        assert!(resolve("order.unknown").is_err());
+        assert!(EventFieldPath::new("0.amounts", ABI, "OrderFilled").is_err());
+    }
+
+    #[test]
+    fn test_check_components() {
+        assert!(EventFieldPath::new("order.amounts[0]", ABI, "OrderFilled").is_ok());
+        assert!(EventFieldPath::new("order.unknown", ABI, "OrderFilled").is_err());
+        assert!(EventFieldPath::new("order.delta.value", ABI, "OrderFilled").is_err());
+        assert!(EventFieldPath::new("order.amounts.id", ABI, "OrderFilled").is_err());
+        assert!(EventFieldPath::new("order.3", ABI, "OrderFilled").is_ok());
+        assert!(EventFieldPath::new("maker.id", ABI, "OrderFilled").is_err());
+    }
+
+    #[test]
+    fn test_token_to_value() {
+        assert_eq!(
+            token_to_value(Token::Bytes(vec![0x01, 0xff])),
+            Value::from("0x01ff")
+        );
+        assert_eq!(
+            token_to_value(Token::Address(
+                H160::from_str("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap()
+            )),
+            Value::from("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed")
+        );
+    }
+}
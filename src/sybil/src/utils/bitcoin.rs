@@ -0,0 +1,185 @@
+use candid::Principal;
+use ic_cdk::api::{
+    call::{call_with_payment128, msg_cycles_refunded128},
+    management_canister::bitcoin::{
+        BitcoinNetwork, GetBalanceRequest, GetUtxosRequest, GetUtxosResponse, Satoshi, UtxoFilter,
+    },
+};
+use thiserror::Error;
+
+use crate::clone_with_state;
+
+// fees of the Bitcoin API, the unused cycles are refunded
+const GET_BALANCE_CYCLES: u128 = 100_000_000;
+const GET_UTXOS_CYCLES: u128 = 10_000_000_000;
+const MAX_UTXOS_PAGES: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum BitcoinError {
+    #[error("Unable to get balance: {0}")]
+    UnableToGetBalance(String),
+    #[error("Unable to get utxos: {0}")]
+    UnableToGetUtxos(String),
+    #[error("Too many utxos, more than {0} pages")]
+    TooManyUtxos(usize),
+}
+
+/// Canister serving the Bitcoin API, the management canister by default.
+/// Can be replaced with a local stand-in for testing
+fn bitcoin_canister() -> Principal {
+    clone_with_state!(bitcoin_canister).unwrap_or(Principal::management_canister())
+}
+
+/// Cycles kept by the Bitcoin API out of the attached ones, must be called right after the call
+fn spent_cycles(attached: u128) -> u128 {
+    attached.saturating_sub(msg_cycles_refunded128())
+}
+
+/// Returns the balance of the address and the cycles spent on the call
+pub async fn get_balance(
+    network: BitcoinNetwork,
+    address: String,
+    min_confirmations: Option<u32>,
+) -> Result<(Satoshi, u128), BitcoinError> {
+    let req = GetBalanceRequest {
+        address,
+        network,
+        min_confirmations,
+    };
+
+    let (balance,) = call_with_payment128::<_, (Satoshi,)>(
+        bitcoin_canister(),
+        "bitcoin_get_balance",
+        (req,),
+        GET_BALANCE_CYCLES,
+    )
+    .await
+    .map_err(|(code, msg)| BitcoinError::UnableToGetBalance(format!("{code:?}: {msg}")))?;
+
+    Ok((balance, spent_cycles(GET_BALANCE_CYCLES)))
+}
+
+/// Sums the values of the address utxos following the pages of the response,
+/// returns the sum and the cycles spent on all the pages
+pub async fn get_utxos_value(
+    network: BitcoinNetwork,
+    address: String,
+    min_confirmations: Option<u32>,
+) -> Result<(Satoshi, u128), BitcoinError> {
+    let mut filter = min_confirmations.map(UtxoFilter::MinConfirmations);
+    let mut sum = UtxosSum::default();
+    let mut cycles = 0;
+
+    loop {
+        let req = GetUtxosRequest {
+            address: address.clone(),
+            network,
+            filter,
+        };
+
+        let (response,) = call_with_payment128::<_, (GetUtxosResponse,)>(
+            bitcoin_canister(),
+            "bitcoin_get_utxos",
+            (req,),
+            GET_UTXOS_CYCLES,
+        )
+        .await
+        .map_err(|(code, msg)| BitcoinError::UnableToGetUtxos(format!("{code:?}: {msg}")))?;
+        cycles += spent_cycles(GET_UTXOS_CYCLES);
+
+        match sum.add_page(response)? {
+            Some(next_page) => filter = Some(next_page),
+            None => return Ok((sum.value, cycles)),
+        }
+    }
+}
+
+/// Sum of the utxos values over the pages of `bitcoin_get_utxos`
+#[derive(Default)]
+struct UtxosSum {
+    value: Satoshi,
+    pages: usize,
+}
+
+impl UtxosSum {
+    /// Adds the utxos of the page, returns the filter of the next page if there is one
+    fn add_page(&mut self, response: GetUtxosResponse) -> Result<Option<UtxoFilter>, BitcoinError> {
+        self.pages += 1;
+        self.value += response
+            .utxos
+            .iter()
+            .map(|utxo| utxo.value)
+            .sum::<Satoshi>();
+
+        match response.next_page {
+            Some(_) if self.pages >= MAX_UTXOS_PAGES => {
+                Err(BitcoinError::TooManyUtxos(MAX_UTXOS_PAGES))
+            }
+            Some(page) => Ok(Some(UtxoFilter::Page(page))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_cdk::api::management_canister::bitcoin::{Outpoint, Utxo};
+
+    use super::*;
+
+    fn page(values: &[Satoshi], next_page: Option<Vec<u8>>) -> GetUtxosResponse {
+        GetUtxosResponse {
+            utxos: values
+                .iter()
+                .enumerate()
+                .map(|(vout, value)| Utxo {
+                    outpoint: Outpoint {
+                        txid: vec![0; 32],
+                        vout: vout as u32,
+                    },
+                    value: *value,
+                    height: 100,
+                })
+                .collect(),
+            tip_block_hash: vec![0; 32],
+            tip_height: 100,
+            next_page,
+        }
+    }
+
+    #[test]
+    fn test_utxos_sum() {
+        let mut sum = UtxosSum::default();
+
+        let next_page = sum.add_page(page(&[1, 2], Some(vec![1]))).unwrap();
+        assert!(matches!(next_page, Some(UtxoFilter::Page(page)) if page == vec![1]));
+
+        let next_page = sum.add_page(page(&[3], None)).unwrap();
+        assert!(next_page.is_none());
+        assert_eq!(sum.value, 6);
+        assert_eq!(sum.pages, 2);
+
+        let mut empty = UtxosSum::default();
+        assert!(empty.add_page(page(&[], None)).unwrap().is_none());
+        assert_eq!(empty.value, 0);
+    }
+
+    #[test]
+    fn test_utxos_pages_limit() {
+        let mut sum = UtxosSum::default();
+        for _ in 1..MAX_UTXOS_PAGES {
+            assert!(sum.add_page(page(&[1], Some(vec![1]))).unwrap().is_some());
+        }
+        assert!(sum.add_page(page(&[1], None)).unwrap().is_none());
+        assert_eq!(sum.value, MAX_UTXOS_PAGES as Satoshi);
+
+        let mut sum = UtxosSum::default();
+        for _ in 1..MAX_UTXOS_PAGES {
+            sum.add_page(page(&[1], Some(vec![1]))).unwrap();
+        }
+        assert!(matches!(
+            sum.add_page(page(&[1], Some(vec![1]))),
+            Err(BitcoinError::TooManyUtxos(MAX_UTXOS_PAGES))
+        ));
+    }
+}
@@ -0,0 +1,174 @@
+use candid::{
+    types::{
+        value::{IDLValue, VariantValue},
+        Label,
+    },
+    IDLArgs,
+};
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+use super::field_path::{self, InvalidFieldPath, PathSegment};
+
+#[derive(Error, Debug)]
+pub enum CandidValueError {
+    #[error("Unable to decode reply: {0}")]
+    InvalidReply(String),
+    #[error("Reply has no values")]
+    EmptyReply,
+    #[error("{0}")]
+    InvalidPath(#[from] InvalidFieldPath),
+    #[error("Field not found: {0}")]
+    FieldNotFound(String),
+}
+
+/// Decodes the candid reply and selects the value by the path in the first reply value.
+/// Record fields and variant cases are matched by name, record fields of tuples by index,
+/// optional values are unwrapped
+pub fn resolve_reply(reply: &[u8], path: Option<&str>) -> Result<Value, CandidValueError> {
+    let args = IDLArgs::from_bytes(reply)
+        .map_err(|err| CandidValueError::InvalidReply(err.to_string()))?;
+
+    let mut value = args
+        .args
+        .into_iter()
+        .next()
+        .ok_or(CandidValueError::EmptyReply)?;
+
+    if let Some(path) = path {
+        let not_found = || CandidValueError::FieldNotFound(path.to_string());
+
+        for segment in field_path::parse(path)? {
+            value = match (unwrap_opt(value), &segment) {
+                (IDLValue::Record(fields), segment) => {
+                    let id = label_id(segment);
+                    fields
+                        .into_iter()
+                        .find(|field| field.id.get_id() == id)
+                        .map(|field| field.val)
+                        .ok_or_else(not_found)?
+                }
+                (IDLValue::Variant(VariantValue(field, _)), segment @ PathSegment::Name(_))
+                    if field.id.get_id() == label_id(segment) =>
+                {
+                    field.val
+                }
+                (IDLValue::Vec(values), PathSegment::Index(index)) => {
+                    values.into_iter().nth(*index).ok_or_else(not_found)?
+                }
+                _ => return Err(not_found()),
+            };
+        }
+    }
+
+    Ok(idl_to_value(unwrap_opt(value)))
+}
+
+fn label_id(segment: &PathSegment) -> u32 {
+    match segment {
+        PathSegment::Name(name) => Label::Named(name.clone()).get_id(),
+        PathSegment::Index(index) => *index as u32,
+    }
+}
+
+fn unwrap_opt(value: IDLValue) -> IDLValue {
+    match value {
+        IDLValue::Opt(value) => unwrap_opt(*value),
+        value => value,
+    }
+}
+
+/// Converts a candid value into a JSON value, `nat`, `int` and 64-bit integers are converted to decimal strings.
+/// The reply is decoded without the interface, so the record fields and variant cases are keyed by the label hash
+pub fn idl_to_value(value: IDLValue) -> Value {
+    match value {
+        IDLValue::Bool(val) => Value::Bool(val),
+        IDLValue::Null | IDLValue::None | IDLValue::Reserved => Value::Null,
+        IDLValue::Text(val) | IDLValue::Number(val) => Value::String(val),
+        IDLValue::Float64(val) => Number::from_f64(val).map_or(Value::Null, Value::Number),
+        IDLValue::Float32(val) => Number::from_f64(val as f64).map_or(Value::Null, Value::Number),
+        IDLValue::Opt(val) => idl_to_value(*val),
+        IDLValue::Vec(values) => Value::Array(values.into_iter().map(idl_to_value).collect()),
+        IDLValue::Record(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|field| (field.id.to_string(), idl_to_value(field.val)))
+                .collect::<Map<_, _>>(),
+        ),
+        IDLValue::Variant(VariantValue(field, _)) => {
+            let mut variant = Map::new();
+            variant.insert(field.id.to_string(), idl_to_value(field.val));
+            Value::Object(variant)
+        }
+        IDLValue::Principal(val) => Value::String(val.to_text()),
+        IDLValue::Nat(val) => Value::String(val.0.to_string()),
+        IDLValue::Int(val) => Value::String(val.0.to_string()),
+        IDLValue::Nat64(val) => Value::String(val.to_string()),
+        IDLValue::Int64(val) => Value::String(val.to_string()),
+        IDLValue::Nat8(val) => Value::from(val),
+        IDLValue::Nat16(val) => Value::from(val),
+        IDLValue::Nat32(val) => Value::from(val),
+        IDLValue::Int8(val) => Value::from(val),
+        IDLValue::Int16(val) => Value::from(val),
+        IDLValue::Int32(val) => Value::from(val),
+        value => Value::String(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::{CandidType, Nat};
+
+    use super::*;
+
+    #[derive(CandidType)]
+    struct Pool {
+        token: String,
+        reserves: (Nat, Nat),
+        fee: Option<u64>,
+    }
+
+    #[derive(CandidType)]
+    enum PoolResult {
+        Ok(Pool),
+        #[allow(dead_code)]
+        Err(String),
+    }
+
+    fn reply() -> Vec<u8> {
+        candid::encode_one(PoolResult::Ok(Pool {
+            token: "ICP".to_string(),
+            reserves: (Nat::from(100u64), Nat::from(250u64)),
+            fee: Some(3),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_reply() {
+        assert_eq!(
+            resolve_reply(&reply(), Some("Ok.token")).unwrap(),
+            Value::from("ICP")
+        );
+        assert_eq!(
+            resolve_reply(&reply(), Some("Ok.reserves.1")).unwrap(),
+            Value::from("250")
+        );
+        assert_eq!(
+            resolve_reply(&reply(), Some("Ok.fee")).unwrap(),
+            Value::from("3")
+        );
+        assert!(resolve_reply(&reply(), Some("Err")).is_err());
+        assert!(resolve_reply(&reply(), Some("Ok.unknown")).is_err());
+    }
+
+    #[test]
+    fn test_resolve_whole_reply() {
+        let reply = candid::encode_one(vec![1u32, 2u32]).unwrap();
+
+        assert_eq!(
+            resolve_reply(&reply, None).unwrap(),
+            Value::from(vec![1, 2])
+        );
+    }
+}
@@ -16,6 +16,8 @@ use crate::{
 pub enum CanisterError {
     #[error("unable to get eth address: {0}")]
     UnableToGetEthAddress(String),
+    #[error("unable to get public key: {0}")]
+    UnableToGetPublicKey(String),
     #[error("address error: {0}")]
     AddressError(#[from] AddressError),
     #[error("balance error: {0}")]
@@ -0,0 +1,85 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error("Invalid field path: {0}")]
+pub struct InvalidFieldPath(pub String);
+
+/// Segment of a path to a nested field
+#[derive(Debug, PartialEq)]
+pub enum PathSegment {
+    Name(String),
+    Index(usize),
+}
+
+/// Splits `order.amounts[1]` into `order`, `amounts`, `1`.
+/// Numeric names select by the index as well, e.g. `order.0`
+pub fn parse(path: &str) -> Result<Vec<PathSegment>, InvalidFieldPath> {
+    let invalid_path = || InvalidFieldPath(path.to_string());
+
+    let mut segments = vec![];
+    for part in path.trim().split('.') {
+        let (name, indexes) = match part.find('[') {
+            Some(pos) => part.split_at(pos),
+            None => (part, ""),
+        };
+
+        if name.is_empty() {
+            if segments.is_empty() || indexes.is_empty() {
+                return Err(invalid_path());
+            }
+        } else if name.chars().all(|c| c.is_ascii_digit()) {
+            segments.push(PathSegment::Index(
+                name.parse().map_err(|_| invalid_path())?,
+            ));
+        } else if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            segments.push(PathSegment::Name(name.to_string()));
+        } else {
+            return Err(invalid_path());
+        }
+
+        let mut indexes = indexes;
+        while !indexes.is_empty() {
+            let (index, rest) = indexes
+                .strip_prefix('[')
+                .and_then(|indexes| indexes.split_once(']'))
+                .ok_or_else(invalid_path)?;
+
+            segments.push(PathSegment::Index(
+                index.trim().parse().map_err(|_| invalid_path())?,
+            ));
+            indexes = rest;
+        }
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("order.amounts[1]").unwrap(),
+            vec![
+                PathSegment::Name("order".to_string()),
+                PathSegment::Name("amounts".to_string()),
+                PathSegment::Index(1),
+            ]
+        );
+        assert_eq!(
+            parse("order.1[0][2]").unwrap(),
+            vec![
+                PathSegment::Name("order".to_string()),
+                PathSegment::Index(1),
+                PathSegment::Index(0),
+                PathSegment::Index(2),
+            ]
+        );
+        assert!(parse("").is_err());
+        assert!(parse("order..amounts").is_err());
+        assert!(parse("order.amounts[1").is_err());
+        assert!(parse("order.amounts[x]").is_err());
+    }
+}
@@ -1,17 +1,24 @@
+pub mod abi;
 pub mod address;
+pub mod bitcoin;
+pub mod candid_value;
 pub mod canister;
 pub mod convertion;
 pub mod encoding;
+pub mod field_path;
 pub mod macros;
 pub mod metrics;
 pub mod nat;
 pub mod parsed_number;
 pub mod processors;
+pub mod resolver;
+pub mod retry;
 pub mod signature;
 pub mod siwe;
 pub mod time;
 pub mod validation;
 pub mod vec;
+pub mod vetkd;
 pub mod web3;
 
 use std::{sync::Arc, time::Duration};
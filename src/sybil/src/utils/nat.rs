@@ -29,3 +29,33 @@ pub fn from_u256(u256: &U256) -> Nat {
 
     Nat(BigUint::from_bytes_be(&buf))
 }
+
+/// Formats the amount in the smallest units as a decimal string, e.g. `150000000` with 8 decimals is `1.5`
+pub fn to_decimal_string(nat: &Nat, decimals: u32) -> String {
+    let digits = format!(
+        "{:0>width$}",
+        nat.0.to_string(),
+        width = decimals as usize + 1
+    );
+    let (integer, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        integer.to_string()
+    } else {
+        format!("{integer}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_decimal_string() {
+        assert_eq!(to_decimal_string(&Nat::from(150_000_000u64), 8), "1.5");
+        assert_eq!(to_decimal_string(&Nat::from(10_000u64), 8), "0.0001");
+        assert_eq!(to_decimal_string(&Nat::from(42u64), 0), "42");
+        assert_eq!(to_decimal_string(&Nat::from(0u64), 8), "0");
+    }
+}
@@ -0,0 +1,517 @@
+use std::str::FromStr;
+
+use jsonpath_rust::JsonPathInst;
+use jsonptr::{Pointer, Resolve};
+use num_bigint::{BigInt, Sign};
+use serde_json::{Number, Value};
+use thiserror::Error;
+
+use super::validation;
+
+/// Bound of the `scale` argument, uint256 has at most 78 digits
+const MAX_SCALE: i32 = 77;
+/// Bound of the exponent of the numbers, beyond the range of f64 the results are not representable anyway
+const MAX_EXPONENT: i64 = 400;
+/// Significant digits kept by the division, more than f64 can represent
+const DIV_PRECISION: i64 = 36;
+
+#[derive(Error, Debug)]
+pub enum ResolverError {
+    #[error("Invalid JSON pointer: {0}")]
+    InvalidPointer(String),
+    #[error("Invalid JSONPath: {0}")]
+    InvalidJsonPath(String),
+    #[error("Unknown operation: {0}")]
+    UnknownOperation(String),
+    #[error("Invalid operation argument: {0}")]
+    InvalidArgument(String),
+    #[error("Value not found: {0}")]
+    ValueNotFound(String),
+    #[error("Value is not an array")]
+    NotAnArray,
+    #[error("Value is not a number")]
+    NotANumber,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Operation result is not a finite number")]
+    InvalidResult,
+}
+
+enum ResolverPath {
+    Pointer(String),
+    JsonPath(String, JsonPathInst),
+}
+
+#[derive(Debug, PartialEq)]
+enum ResolverOp {
+    First,
+    Last,
+    Invert,
+    Mul(Decimal),
+    Div(Decimal),
+    Scale(i32),
+}
+
+/// Exact decimal `mantissa * 10^-scale`, the operations are done on it instead of f64,
+/// so they don't lose the precision of the large integers and the decimal fractions
+#[derive(Clone, Debug, PartialEq)]
+struct Decimal {
+    mantissa: BigInt,
+    scale: i64,
+}
+
+/// Extracts a value from a JSON response.
+///
+/// Syntax: `<path> | <op> | <op> ...`, where the path is either a JSON pointer (`/data/0/price`)
+/// or a JSONPath expression starting with `$` (`$.data[?(@.symbol == 'BTC')].price`).
+/// JSONPath expressions matching several values produce an array.
+///
+/// Supported operations:
+/// - `first`, `last` - pick the first or the last element of an array
+/// - `invert` - `1 / x`
+/// - `mul(n)`, `div(n)` - multiply or divide by a constant
+/// - `scale(n)` - multiply by `10^n`, `n` can be negative, from -77 to 77
+///
+/// The operations are exact, except for the division which keeps 36 significant digits,
+/// their result is a JSON number
+pub struct Resolver {
+    path: ResolverPath,
+    ops: Vec<ResolverOp>,
+}
+
+impl FromStr for Resolver {
+    type Err = ResolverError;
+
+    fn from_str(resolver: &str) -> Result<Self, Self::Err> {
+        let mut parts = split_pipeline(resolver).into_iter();
+
+        let path = parts.next().unwrap_or_default();
+        let path = if path.starts_with('$') {
+            let inst = JsonPathInst::from_str(&path).map_err(ResolverError::InvalidJsonPath)?;
+            ResolverPath::JsonPath(path, inst)
+        } else {
+            if !validation::RATE_RESOLVER.is_match(&path) {
+                return Err(ResolverError::InvalidPointer(path));
+            }
+
+            ResolverPath::Pointer(path)
+        };
+
+        let ops = parts
+            .map(|op| ResolverOp::from_str(&op))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { path, ops })
+    }
+}
+
+impl Resolver {
+    pub fn resolve(&self, data: &Value) -> Result<Value, ResolverError> {
+        let mut value = match &self.path {
+            ResolverPath::Pointer(path) => {
+                let ptr = Pointer::try_from(path.clone())
+                    .map_err(|err| ResolverError::InvalidPointer(format!("{err:?}")))?;
+
+                data.resolve(&ptr)
+                    .map_err(|err| ResolverError::ValueNotFound(format!("{err:?}")))?
+                    .clone()
+            }
+            ResolverPath::JsonPath(path, inst) => {
+                let mut values = inst
+                    .find_slice(data)
+                    .into_iter()
+                    .map(|value| (*value).clone())
+                    .collect::<Vec<_>>();
+
+                match values.len() {
+                    0 => return Err(ResolverError::ValueNotFound(path.clone())),
+                    1 => values.remove(0),
+                    _ => Value::Array(values),
+                }
+            }
+        };
+
+        for op in &self.ops {
+            value = op.apply(value)?;
+        }
+
+        Ok(value)
+    }
+}
+
+impl FromStr for ResolverOp {
+    type Err = ResolverError;
+
+    fn from_str(op: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match op.split_once('(') {
+            Some((name, arg)) => {
+                let arg = arg
+                    .strip_suffix(')')
+                    .ok_or_else(|| ResolverError::UnknownOperation(op.to_string()))?;
+
+                (name.trim(), Some(arg.trim()))
+            }
+            None => (op, None),
+        };
+
+        let invalid_arg = || ResolverError::InvalidArgument(op.to_string());
+
+        match (name, arg) {
+            ("first", None) => Ok(ResolverOp::First),
+            ("last", None) => Ok(ResolverOp::Last),
+            ("invert", None) => Ok(ResolverOp::Invert),
+            ("mul", Some(arg)) => Ok(ResolverOp::Mul(arg.parse().map_err(|_| invalid_arg())?)),
+            ("div", Some(arg)) => {
+                let divisor: Decimal = arg.parse().map_err(|_| invalid_arg())?;
+                if divisor.is_zero() {
+                    return Err(invalid_arg());
+                }
+
+                Ok(ResolverOp::Div(divisor))
+            }
+            ("scale", Some(arg)) => {
+                let n: i32 = arg.parse().map_err(|_| invalid_arg())?;
+                if !(-MAX_SCALE..=MAX_SCALE).contains(&n) {
+                    return Err(invalid_arg());
+                }
+
+                Ok(ResolverOp::Scale(n))
+            }
+            _ => Err(ResolverError::UnknownOperation(op.to_string())),
+        }
+    }
+}
+
+impl ResolverOp {
+    fn apply(&self, value: Value) -> Result<Value, ResolverError> {
+        match self {
+            ResolverOp::First | ResolverOp::Last => {
+                let Value::Array(mut values) = value else {
+                    return Err(ResolverError::NotAnArray);
+                };
+
+                let value = match self {
+                    ResolverOp::First => values.into_iter().next(),
+                    _ => values.pop(),
+                };
+
+                value.ok_or_else(|| ResolverError::ValueNotFound("array is empty".to_string()))
+            }
+            ResolverOp::Invert => Decimal::from(1).div_by(&Decimal::try_from(&value)?)?,
+            ResolverOp::Mul(n) => Decimal::try_from(&value)?.mul_by(n),
+            ResolverOp::Div(n) => Decimal::try_from(&value)?.div_by(n)?,
+            ResolverOp::Scale(n) => Decimal::try_from(&value)?.scale_by(*n),
+        }
+        .to_value()
+    }
+}
+
+impl Decimal {
+    fn is_zero(&self) -> bool {
+        self.mantissa.sign() == Sign::NoSign
+    }
+
+    fn digits(&self) -> i64 {
+        self.mantissa.magnitude().to_string().len() as i64
+    }
+
+    fn mul_by(&self, other: &Decimal) -> Decimal {
+        Decimal {
+            mantissa: &self.mantissa * &other.mantissa,
+            scale: self.scale + other.scale,
+        }
+    }
+
+    /// Quotient with at least `DIV_PRECISION` significant digits, truncated
+    fn div_by(&self, divisor: &Decimal) -> Result<Decimal, ResolverError> {
+        if divisor.is_zero() {
+            return Err(ResolverError::DivisionByZero);
+        }
+
+        let shift = (DIV_PRECISION + divisor.digits() - self.digits()).max(0);
+        Ok(Decimal {
+            mantissa: &self.mantissa * pow10(shift) / &divisor.mantissa,
+            scale: self.scale - divisor.scale + shift,
+        })
+    }
+
+    fn scale_by(&self, n: i32) -> Decimal {
+        Decimal {
+            mantissa: self.mantissa.clone(),
+            scale: self.scale - n as i64,
+        }
+    }
+
+    /// JSON number of the decimal, integers are kept exact if they fit into u64 or i64,
+    /// the others are rounded to the nearest f64
+    fn to_value(&self) -> Result<Value, ResolverError> {
+        if self.is_zero() {
+            return Ok(Value::from(0));
+        }
+
+        if self.digits() - self.scale > MAX_EXPONENT {
+            return Err(ResolverError::InvalidResult);
+        }
+
+        let number = if self.scale <= 0 {
+            (&self.mantissa * pow10(-self.scale)).to_string()
+        } else {
+            let scale = self.scale as usize;
+            let magnitude = self.mantissa.magnitude().to_string();
+            let digits = format!("{magnitude:0>width$}", width = scale + 1);
+            let (integer, fraction) = digits.split_at(digits.len() - scale);
+            let fraction = fraction.trim_end_matches('0');
+
+            let sign = if self.mantissa.sign() == Sign::Minus {
+                "-"
+            } else {
+                ""
+            };
+
+            if fraction.is_empty() {
+                format!("{sign}{integer}")
+            } else {
+                format!("{sign}{integer}.{fraction}")
+            }
+        };
+
+        serde_json::from_str::<Number>(&number)
+            .map(Value::Number)
+            .map_err(|_| ResolverError::InvalidResult)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(number: i64) -> Self {
+        Decimal {
+            mantissa: BigInt::from(number),
+            scale: 0,
+        }
+    }
+}
+
+impl TryFrom<&Value> for Decimal {
+    type Error = ResolverError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) => number.to_string().parse(),
+            Value::String(string) => string.parse(),
+            _ => Err(ResolverError::NotANumber),
+        }
+    }
+}
+
+/// Parses the decimal notation with an optional exponent, e.g. `-12.5` or `1.5e-7`,
+/// `NaN` and `inf` are not numbers
+impl FromStr for Decimal {
+    type Err = ResolverError;
+
+    fn from_str(number: &str) -> Result<Self, Self::Err> {
+        let number = number.trim();
+        let (number, exponent) = match number.split_once(['e', 'E']) {
+            Some((number, exponent)) => (
+                number,
+                exponent
+                    .parse::<i64>()
+                    .map_err(|_| ResolverError::NotANumber)?,
+            ),
+            None => (number, 0),
+        };
+
+        if exponent.unsigned_abs() > MAX_EXPONENT as u64 {
+            return Err(ResolverError::NotANumber);
+        }
+
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let unsigned = integer
+            .strip_prefix(['-', '+'])
+            .unwrap_or(integer)
+            .to_string()
+            + fraction;
+
+        if unsigned.is_empty() || !unsigned.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(ResolverError::NotANumber);
+        }
+
+        let mantissa = BigInt::from_str(&format!("{integer}{fraction}"))
+            .map_err(|_| ResolverError::NotANumber)?;
+
+        Ok(Decimal {
+            mantissa,
+            scale: fraction.len() as i64 - exponent,
+        })
+    }
+}
+
+fn pow10(exponent: i64) -> BigInt {
+    BigInt::from(10).pow(exponent as u32)
+}
+
+/// Splits the resolver by `|`, ignoring the ones inside quotes and brackets of JSONPath filters
+fn split_pipeline(resolver: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+
+    for c in resolver.chars() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('[' | '(', None) => depth += 1,
+            (']' | ')', None) => depth = depth.saturating_sub(1),
+            ('|', None) if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+
+        current.push(c);
+    }
+
+    parts.push(current.trim().to_string());
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resolve(resolver: &str, data: &Value) -> Value {
+        Resolver::from_str(resolver)
+            .expect("invalid resolver")
+            .resolve(data)
+            .expect("unable to resolve")
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        let data = json!({"data": {"price": "42.5"}});
+
+        assert_eq!(resolve("/data/price", &data), json!("42.5"));
+        assert_eq!(resolve("/data/price | mul(2)", &data), json!(85));
+        assert_eq!(resolve("/data/price | mul(0.1)", &data), json!(4.25));
+    }
+
+    #[test]
+    fn test_json_path_filter() {
+        let data = json!({"data": [
+            {"symbol": "ETH", "price": 2000},
+            {"symbol": "BTC", "price": 40000},
+        ]});
+
+        assert_eq!(
+            resolve("$.data[?(@.symbol == 'BTC')].price", &data),
+            json!(40000)
+        );
+        assert_eq!(resolve("$.data[*].price | last", &data), json!(40000));
+        assert_eq!(
+            resolve("$.data[*].price | first | invert", &data),
+            json!(0.0005)
+        );
+    }
+
+    #[test]
+    fn test_scale() {
+        let data = json!({"value": 12345});
+
+        assert_eq!(resolve("/value | scale(-2)", &data), json!(123.45));
+        assert_eq!(resolve("/value | scale(2) | div(5)", &data), json!(246900));
+
+        // the large integers are scaled exactly
+        let data = json!({"value": "123456789012345678901234567890"});
+        assert_eq!(
+            resolve("/value | scale(-27)", &data),
+            json!(123.45678901234568)
+        );
+        assert_eq!(
+            resolve("/value | scale(-20) | div(3)", &data),
+            json!(411522630.04115224)
+        );
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(
+            Decimal::from_str("-12.50").unwrap(),
+            Decimal {
+                mantissa: BigInt::from(-1250),
+                scale: 2
+            }
+        );
+        assert_eq!(
+            Decimal::from_str("1.5e-7").unwrap(),
+            Decimal {
+                mantissa: BigInt::from(15),
+                scale: 8
+            }
+        );
+        assert_eq!(
+            Decimal::from_str("-0.5").unwrap().to_value().unwrap(),
+            json!(-0.5)
+        );
+        assert_eq!(
+            Decimal::from(1)
+                .div_by(&Decimal::from(3))
+                .unwrap()
+                .to_value()
+                .unwrap(),
+            json!(0.3333333333333333)
+        );
+
+        for number in ["NaN", "inf", "-inf", "", ".", "1.-5", "1e", "1e100000"] {
+            assert!(Decimal::from_str(number).is_err(), "{number}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_results() {
+        let data = json!({"zero": 0, "huge": "1e400", "nan": "NaN"});
+
+        let resolve_err = |resolver: &str| {
+            Resolver::from_str(resolver)
+                .expect("invalid resolver")
+                .resolve(&data)
+                .unwrap_err()
+        };
+
+        assert!(matches!(
+            resolve_err("/zero | invert"),
+            ResolverError::DivisionByZero
+        ));
+        assert!(matches!(
+            resolve_err("/huge | scale(77)"),
+            ResolverError::InvalidResult
+        ));
+        assert!(matches!(
+            resolve_err("/nan | mul(2)"),
+            ResolverError::NotANumber
+        ));
+    }
+
+    #[test]
+    fn test_invalid_resolvers() {
+        assert!(Resolver::from_str("/value | unknown").is_err());
+        assert!(Resolver::from_str("/value | div(0)").is_err());
+        assert!(Resolver::from_str("/value | div(0.000)").is_err());
+        assert!(Resolver::from_str("/value | mul(abc)").is_err());
+        assert!(Resolver::from_str("/value | mul(NaN)").is_err());
+        assert!(Resolver::from_str("/value | mul(inf)").is_err());
+        assert!(Resolver::from_str("/value | scale(78)").is_err());
+        assert!(Resolver::from_str("/value | scale(-2147483648)").is_err());
+        assert!(Resolver::from_str("$.data[").is_err());
+    }
+
+    #[test]
+    fn test_split_pipeline() {
+        assert_eq!(
+            split_pipeline("$.a[?(@.b == 'x|y' || @.c)] | last"),
+            vec!["$.a[?(@.b == 'x|y' || @.c)]", "last"]
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use candid::CandidType;
+use ic_cdk::api::call::RejectionCode;
+use ic_web3_rs::error::TransportError;
+use serde::{Deserialize, Serialize};
+
+use super::{sleep, time};
+use crate::log;
+
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const DEFAULT_INITIAL_DELAY_MS: u64 = 1000;
+const DEFAULT_MAX_DELAY_MS: u64 = 10_000;
+const DEFAULT_MULTIPLIER: u32 = 2;
+const MAX_RETRIES: u32 = 10;
+const MAX_DELAY_MS: u64 = 60_000;
+const MAX_MULTIPLIER: u32 = 10;
+
+/// Reject messages of the outcalls whose responses differed across the replicas,
+/// caused by a non-deterministic response of the endpoint rather than by the proxy or the network.
+/// They are rejected with `SysTransient` as the timeouts, so only the message tells them apart
+const NO_CONSENSUS_REJECTS: [&str; 2] = [
+    "Canister http responses were different across replicas",
+    "No consensus could be reached",
+];
+
+/// Errors which tell whether the failed call can succeed on a retry
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+/// Whether the reject message is of the replicas disagreement, see `NO_CONSENSUS_REJECTS`
+fn is_no_consensus_reject(msg: &str) -> bool {
+    NO_CONSENSUS_REJECTS
+        .iter()
+        .any(|reject| msg.contains(reject))
+}
+
+/// Reject of an outcall or a canister call, kept with its code by the transports
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reject {
+    pub code: RejectionCode,
+    pub msg: String,
+}
+
+impl Reject {
+    pub fn new(code: RejectionCode, msg: String) -> Self {
+        Self { code, msg }
+    }
+
+    /// Timeouts, connection failures and the replicas disagreement
+    pub fn is_transient(&self) -> bool {
+        self.code == RejectionCode::SysTransient
+    }
+
+    pub fn is_no_consensus(&self) -> bool {
+        self.is_transient() && is_no_consensus_reject(&self.msg)
+    }
+
+    /// Transient rejects caused by the proxy or the network rather than by the endpoint,
+    /// the request can succeed through another rpc wrapper
+    pub fn is_network_failure(&self) -> bool {
+        self.is_transient() && !self.is_no_consensus()
+    }
+}
+
+impl Retryable for Reject {
+    fn is_retryable(&self) -> bool {
+        self.is_transient()
+    }
+}
+
+impl From<Reject> for ic_web3_rs::Error {
+    fn from(reject: Reject) -> Self {
+        ic_web3_rs::Error::Transport(TransportError::Message(format!(
+            "{:?}: {}",
+            reject.code, reject.msg
+        )))
+    }
+}
+
+/// Budget of the attempts of the calls made for one source, shared by the rpc wrappers,
+/// the providers and the retries, which multiply otherwise.
+/// Keeps the rejects the calls have finally failed with, after their retries
+#[derive(Clone, Debug)]
+pub struct Attempts {
+    left: Arc<AtomicU32>,
+    rejects: Arc<Mutex<Vec<Reject>>>,
+}
+
+impl Attempts {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            left: Arc::new(AtomicU32::new(max_attempts)),
+            rejects: Arc::default(),
+        }
+    }
+
+    /// Budget of the calls not bound to a source, e.g. of the balances rpc
+    pub fn unlimited() -> Self {
+        Self::new(u32::MAX)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.left.load(Ordering::Relaxed) == 0
+    }
+
+    /// Counts the made attempt
+    pub fn take(&self) {
+        let _ = self
+            .left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            });
+    }
+
+    pub fn add_reject(&self, reject: Reject) {
+        self.rejects
+            .lock()
+            .expect("rejects lock should not be poisoned")
+            .push(reject);
+    }
+
+    /// Returns the rejects kept since the last call
+    pub fn take_rejects(&self) -> Vec<Reject> {
+        std::mem::take(
+            &mut *self
+                .rejects
+                .lock()
+                .expect("rejects lock should not be poisoned"),
+        )
+    }
+}
+
+/// Retries with exponential backoff, the unset fields take the defaults.
+/// `timeout_ms` limits the total time of the retries, no retry is scheduled past it
+#[derive(Clone, Copy, Debug, Default, PartialEq, CandidType, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_retries: Option<u32>,
+    pub initial_delay_ms: Option<u64>,
+    pub max_delay_ms: Option<u64>,
+    pub multiplier: Option<u32>,
+    pub timeout_ms: Option<u64>,
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_retries() > MAX_RETRIES {
+            return Err(format!("at most {MAX_RETRIES} retries are allowed"));
+        }
+
+        if self.initial_delay_ms() > self.max_delay_ms() || self.max_delay_ms() > MAX_DELAY_MS {
+            return Err(format!(
+                "delays should not exceed max_delay_ms, which is at most {MAX_DELAY_MS}ms"
+            ));
+        }
+
+        if !(1..=MAX_MULTIPLIER).contains(&self.multiplier()) {
+            return Err(format!("multiplier should be from 1 to {MAX_MULTIPLIER}"));
+        }
+
+        Ok(())
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+    }
+
+    fn initial_delay_ms(&self) -> u64 {
+        self.initial_delay_ms.unwrap_or(DEFAULT_INITIAL_DELAY_MS)
+    }
+
+    fn max_delay_ms(&self) -> u64 {
+        self.max_delay_ms
+            .unwrap_or(DEFAULT_MAX_DELAY_MS.max(self.initial_delay_ms()))
+    }
+
+    fn multiplier(&self) -> u32 {
+        self.multiplier.unwrap_or(DEFAULT_MULTIPLIER)
+    }
+
+    /// Delay before the retry, `retry` starts from 0
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = (self.multiplier() as u64)
+            .checked_pow(retry)
+            .and_then(|factor| factor.checked_mul(self.initial_delay_ms()))
+            .unwrap_or(u64::MAX);
+
+        Duration::from_millis(delay.min(self.max_delay_ms()))
+    }
+}
+
+/// Calls `f` until it succeeds, fails with a non-retryable error or the policy is exhausted
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, f: F) -> Result<T, E>
+where
+    E: Retryable + Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_with_attempts(policy, &Attempts::unlimited(), f).await
+}
+
+/// Same as `retry`, every attempt is counted in `attempts` and no retry is made once they are exhausted
+pub async fn retry_with_attempts<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    attempts: &Attempts,
+    mut f: F,
+) -> Result<T, E>
+where
+    E: Retryable + Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let deadline = policy
+        .timeout_ms
+        .map(|timeout| time::in_millis().saturating_add(timeout));
+
+    let mut retry = 0;
+    loop {
+        let result = f().await;
+        attempts.take();
+
+        let err = match &result {
+            Err(err)
+                if err.is_retryable()
+                    && retry < policy.max_retries()
+                    && !attempts.is_exhausted() =>
+            {
+                err
+            }
+            _ => return result,
+        };
+
+        let delay = policy.delay(retry);
+        if deadline.is_some_and(|deadline| {
+            time::in_millis().saturating_add(delay.as_millis() as u64) > deadline
+        }) {
+            return result;
+        }
+
+        log!(
+            "[RETRY] attempt {} failed: {:?}, retrying in {}ms",
+            retry + 1,
+            err,
+            delay.as_millis()
+        );
+
+        sleep(delay).await;
+        retry += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_millis(1000));
+        assert_eq!(policy.delay(1), Duration::from_millis(2000));
+        assert_eq!(policy.delay(3), Duration::from_millis(8000));
+        assert_eq!(policy.delay(4), Duration::from_millis(10_000));
+        assert_eq!(policy.delay(100), Duration::from_millis(10_000));
+
+        let policy = RetryPolicy {
+            initial_delay_ms: Some(500),
+            multiplier: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(3), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RetryPolicy::default().validate().is_ok());
+        assert!(RetryPolicy {
+            initial_delay_ms: Some(20_000),
+            ..Default::default()
+        }
+        .validate()
+        .is_ok());
+
+        assert!(RetryPolicy {
+            max_retries: Some(MAX_RETRIES + 1),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(RetryPolicy {
+            initial_delay_ms: Some(2000),
+            max_delay_ms: Some(1000),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(RetryPolicy {
+            multiplier: Some(0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_reject() {
+        let timeout = Reject::new(RejectionCode::SysTransient, "Timeout expired".to_string());
+        assert!(timeout.is_retryable());
+        assert!(timeout.is_network_failure());
+        assert!(!timeout.is_no_consensus());
+
+        let no_consensus = Reject::new(
+            RejectionCode::SysTransient,
+            "No consensus could be reached. Replicas had different responses.".to_string(),
+        );
+        assert!(no_consensus.is_retryable());
+        assert!(no_consensus.is_no_consensus());
+        assert!(!no_consensus.is_network_failure());
+
+        // the code decides, not the message
+        let rejected = Reject::new(RejectionCode::CanisterReject, "Timeout expired".to_string());
+        assert!(!rejected.is_retryable());
+        assert!(!rejected.is_network_failure());
+    }
+
+    #[test]
+    fn test_attempts() {
+        let attempts = Attempts::new(2);
+        let shared = attempts.clone();
+        attempts.take();
+        assert!(!shared.is_exhausted());
+        shared.take();
+        assert!(attempts.is_exhausted());
+        attempts.take();
+        assert!(attempts.is_exhausted());
+
+        let reject = Reject::new(RejectionCode::SysTransient, "Timeout expired".to_string());
+        shared.add_reject(reject.clone());
+        assert_eq!(attempts.take_rejects(), vec![reject]);
+        assert!(attempts.take_rejects().is_empty());
+    }
+}
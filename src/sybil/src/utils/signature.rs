@@ -1,12 +1,53 @@
-use candid::Principal;
+use candid::{CandidType, Principal};
 use ic_cdk::api::{
-    call::{call_with_payment, CallResult},
+    call::{call, call_with_payment, CallResult},
     management_canister::ecdsa::{SignWithEcdsaArgument, SignWithEcdsaResponse},
 };
 use ic_web3_rs::{ic::recover_address, types::H160};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 const ECDSA_SIGN_CYCLES: u64 = 23_000_000_000;
+const SCHNORR_SIGN_CYCLES: u64 = 26_200_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SchnorrAlgorithm {
+    #[serde(rename = "bip340secp256k1")]
+    Bip340Secp256k1,
+    #[serde(rename = "ed25519")]
+    Ed25519,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct SchnorrKeyId {
+    pub algorithm: SchnorrAlgorithm,
+    pub name: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct SignWithSchnorrArgument {
+    pub message: Vec<u8>,
+    pub derivation_path: Vec<Vec<u8>>,
+    pub key_id: SchnorrKeyId,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct SignWithSchnorrResponse {
+    pub signature: Vec<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct SchnorrPublicKeyArgument {
+    pub canister_id: Option<Principal>,
+    pub derivation_path: Vec<Vec<u8>>,
+    pub key_id: SchnorrKeyId,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct SchnorrPublicKeyResponse {
+    pub public_key: Vec<u8>,
+    pub chain_code: Vec<u8>,
+}
 
 #[derive(Error, Debug)]
 pub enum SignatureError {
@@ -40,3 +81,24 @@ pub async fn sign(args: SignWithEcdsaArgument) -> CallResult<(SignWithEcdsaRespo
     )
     .await
 }
+
+pub async fn sign_schnorr(args: SignWithSchnorrArgument) -> CallResult<(SignWithSchnorrResponse,)> {
+    call_with_payment(
+        Principal::management_canister(),
+        "sign_with_schnorr",
+        (args,),
+        SCHNORR_SIGN_CYCLES,
+    )
+    .await
+}
+
+pub async fn schnorr_public_key(
+    args: SchnorrPublicKeyArgument,
+) -> CallResult<(SchnorrPublicKeyResponse,)> {
+    call(
+        Principal::management_canister(),
+        "schnorr_public_key",
+        (args,),
+    )
+    .await
+}
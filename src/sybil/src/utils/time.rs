@@ -12,3 +12,8 @@ pub async fn wait(delay: Seconds) {
 pub fn in_seconds() -> Timestamp {
     time() / 1_000_000_000
 }
+
+#[inline]
+pub fn in_millis() -> u64 {
+    time() / 1_000_000
+}
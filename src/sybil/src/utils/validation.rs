@@ -1,8 +1,14 @@
+use std::str::FromStr;
+
 use candid::Nat;
+use ic_cdk::api::management_canister::http_request::HttpHeader;
 use lazy_static::lazy_static;
 use regex::Regex;
 use validator::ValidationError;
 
+use super::resolver::Resolver;
+use crate::types::response_format::ResponseFormat;
+
 const MIN_UPDATE_FREQ: u64 = 60 * 5;
 
 lazy_static! {
@@ -17,3 +23,24 @@ pub fn validate_update_freq(update_freq: &Nat) -> Result<(), ValidationError> {
     }
     Ok(())
 }
+
+pub fn validate_http_headers(headers: &Vec<HttpHeader>) -> Result<(), ValidationError> {
+    if headers.iter().any(|header| header.name.trim().is_empty()) {
+        return Err(ValidationError::new("header name is empty"));
+    }
+    Ok(())
+}
+
+pub fn validate_resolver(resolver: &str) -> Result<(), ValidationError> {
+    if Resolver::from_str(resolver).is_err() {
+        return Err(ValidationError::new("invalid resolver"));
+    }
+    Ok(())
+}
+
+pub fn validate_response_format(format: &ResponseFormat) -> Result<(), ValidationError> {
+    if format.validate().is_err() {
+        return Err(ValidationError::new("invalid response format"));
+    }
+    Ok(())
+}
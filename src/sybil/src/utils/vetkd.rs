@@ -0,0 +1,162 @@
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, Scalar};
+use candid::{CandidType, Principal};
+use ic_cdk::api::{call::call_with_payment128, management_canister::main::raw_rand};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+const VETKD_DERIVE_KEY_CYCLES: u128 = 26_153_846_153;
+const G1_SIZE: usize = 48;
+const G2_SIZE: usize = 96;
+const ENCRYPTED_KEY_SIZE: usize = G1_SIZE + G2_SIZE + G1_SIZE;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum VetKDCurve {
+    #[serde(rename = "bls12_381_g2")]
+    Bls12381G2,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct VetKDKeyId {
+    pub curve: VetKDCurve,
+    pub name: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct VetKDDeriveKeyArgument {
+    pub input: Vec<u8>,
+    pub context: Vec<u8>,
+    pub key_id: VetKDKeyId,
+    pub transport_public_key: Vec<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct VetKDDeriveKeyResponse {
+    pub encrypted_key: Vec<u8>,
+}
+
+#[derive(Error, Debug)]
+pub enum VetKDError {
+    #[error("Unable to get randomness: {0}")]
+    UnableToGetRandomness(String),
+    #[error("Unable to derive key: {0}")]
+    UnableToDeriveKey(String),
+    #[error("Invalid encrypted key")]
+    InvalidEncryptedKey,
+}
+
+/// Derives the 32 bytes symmetric key of the canister for the `context` and `input`.
+/// The same arguments always give the same key, which never leaves the canister heap
+pub async fn derive_symmetric_key(
+    key_name: &str,
+    context: &[u8],
+    input: &[u8],
+) -> Result<[u8; 32], VetKDError> {
+    let (randomness,) = raw_rand()
+        .await
+        .map_err(|(_, msg)| VetKDError::UnableToGetRandomness(msg))?;
+
+    let mut wide = [0u8; 64];
+    let len = randomness.len().min(wide.len());
+    wide[..len].copy_from_slice(&randomness[..len]);
+    let transport_secret_key = Scalar::from_bytes_wide(&wide);
+    let transport_public_key = G1Affine::from(G1Affine::generator() * transport_secret_key);
+
+    let args = VetKDDeriveKeyArgument {
+        input: input.to_vec(),
+        context: context.to_vec(),
+        key_id: VetKDKeyId {
+            curve: VetKDCurve::Bls12381G2,
+            name: key_name.to_string(),
+        },
+        transport_public_key: transport_public_key.to_compressed().to_vec(),
+    };
+
+    let (response,): (VetKDDeriveKeyResponse,) = call_with_payment128(
+        Principal::management_canister(),
+        "vetkd_derive_key",
+        (args,),
+        VETKD_DERIVE_KEY_CYCLES,
+    )
+    .await
+    .map_err(|(code, msg)| VetKDError::UnableToDeriveKey(format!("{code:?}: {msg}")))?;
+
+    let key = decrypt_key(&response.encrypted_key, &transport_secret_key)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(context);
+    hasher.update(key.to_compressed());
+    Ok(hasher.finalize().into())
+}
+
+/// The encrypted key is `(c1, c2, c3) = (g1^r, g2^r, k * tpk^r)`, so `k = c3 - c1 * tsk`
+fn decrypt_key(
+    encrypted_key: &[u8],
+    transport_secret_key: &Scalar,
+) -> Result<G1Affine, VetKDError> {
+    if encrypted_key.len() != ENCRYPTED_KEY_SIZE {
+        return Err(VetKDError::InvalidEncryptedKey);
+    }
+
+    let (c1, rest) = encrypted_key.split_at(G1_SIZE);
+    let (c2, c3) = rest.split_at(G2_SIZE);
+
+    let c1 = g1_from_bytes(c1)?;
+    let c2 = Option::<G2Affine>::from(G2Affine::from_compressed(
+        c2.try_into().map_err(|_| VetKDError::InvalidEncryptedKey)?,
+    ))
+    .ok_or(VetKDError::InvalidEncryptedKey)?;
+    let c3 = g1_from_bytes(c3)?;
+
+    // c1 and c2 should share the same randomness
+    if pairing(&c1, &G2Affine::generator()) != pairing(&G1Affine::generator(), &c2) {
+        return Err(VetKDError::InvalidEncryptedKey);
+    }
+
+    Ok(G1Affine::from(
+        G1Projective::from(c3) - c1 * transport_secret_key,
+    ))
+}
+
+fn g1_from_bytes(bytes: &[u8]) -> Result<G1Affine, VetKDError> {
+    Option::from(G1Affine::from_compressed(
+        bytes
+            .try_into()
+            .map_err(|_| VetKDError::InvalidEncryptedKey)?,
+    ))
+    .ok_or(VetKDError::InvalidEncryptedKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_key() {
+        let key = G1Affine::from(G1Affine::generator() * Scalar::from(7u64));
+        let transport_secret_key = Scalar::from(11u64);
+        let r = Scalar::from(13u64);
+
+        let c1 = G1Affine::from(G1Affine::generator() * r);
+        let c2 = G2Affine::from(G2Affine::generator() * r);
+        let c3 = G1Affine::from(
+            G1Projective::from(key) + G1Affine::generator() * (transport_secret_key * r),
+        );
+
+        let mut encrypted_key = c1.to_compressed().to_vec();
+        encrypted_key.extend(c2.to_compressed());
+        encrypted_key.extend(c3.to_compressed());
+
+        assert_eq!(
+            decrypt_key(&encrypted_key, &transport_secret_key).unwrap(),
+            key
+        );
+
+        // c2 with other randomness
+        let c2 = G2Affine::from(G2Affine::generator() * Scalar::from(17u64));
+        encrypted_key[G1_SIZE..G1_SIZE + G2_SIZE].copy_from_slice(&c2.to_compressed());
+        assert!(decrypt_key(&encrypted_key, &transport_secret_key).is_err());
+
+        assert!(decrypt_key(&[0; 10], &transport_secret_key).is_err());
+    }
+}
@@ -18,6 +18,9 @@ use jsonrpc_core::{Call, Output, Params, Request};
 use serde::Deserialize;
 use serde_json::Value;
 
+use super::multi_provider_transport::ProviderError;
+use crate::utils::retry::Reject;
+
 const MAX_CYCLES: u128 = 60_000_000_000;
 const DEFAULT_MAX_RESPONSE_BYTES: u64 = 100000;
 
@@ -26,15 +29,17 @@ const DEFAULT_MAX_RESPONSE_BYTES: u64 = 100000;
 pub struct EVMCanisterTransport {
     rpc_url: String,
     evm_rpc_canister: Principal,
+    chain_id: Option<u64>,
     max_response_bytes: u64,
 }
 
 impl EVMCanisterTransport {
     /// Create new ICEthRpc instance
-    pub fn new(rpc_url: String, evm_rpc_canister: Principal) -> Self {
+    pub fn new(rpc_url: String, evm_rpc_canister: Principal, chain_id: Option<u64>) -> Self {
         Self {
             rpc_url,
             evm_rpc_canister,
+            chain_id,
             max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
         }
     }
@@ -43,6 +48,64 @@ impl EVMCanisterTransport {
     pub fn next_id(&self) -> RequestId {
         1
     }
+
+    /// Same as `send`, but the reject of the EVM RPC canister call keeps its code
+    pub fn send_call(&self, call: Call) -> BoxFuture<'static, Result<Value, ProviderError>> {
+        let service: RpcService = RpcService::Custom(RpcApi {
+            url: self.rpc_url.clone(),
+            headers: None,
+        });
+
+        let json_rpc_payload = serde_json::to_string(&Request::Single(call.clone())).unwrap();
+
+        let ic_eth_rpc = self.evm_rpc_canister;
+        let max_response_bytes = self.max_response_bytes;
+
+        match call {
+            Call::MethodCall(method_call) => match method_call.method.as_str() {
+                "eth_sendRawTransaction" => {
+                    let raw_tx = match &method_call.params {
+                        Params::Array(arr) => arr
+                            .first()
+                            .and_then(Value::as_str)
+                            .and_then(|raw_tx| hex::decode(raw_tx.trim_start_matches("0x")).ok()),
+                        _ => None,
+                    };
+
+                    let (Some(raw_tx), Some(chain_id)) = (raw_tx, self.chain_id) else {
+                        return Box::pin(async {
+                            Err(ProviderError::Failed(ic_web3_rs::Error::Transport(
+                                TransportError::Message(
+                                    "raw transaction and chain id are required".to_string(),
+                                ),
+                            )))
+                        });
+                    };
+
+                    Box::pin(send_raw_tx(
+                        ic_eth_rpc,
+                        RpcServices::Custom {
+                            chain_id,
+                            services: vec![RpcApi {
+                                url: self.rpc_url.clone(),
+                                headers: None,
+                            }],
+                        },
+                        None,
+                        raw_tx,
+                    ))
+                }
+                _ => Box::pin(async move {
+                    execute_canister_call(ic_eth_rpc, service, json_rpc_payload, max_response_bytes)
+                        .await
+                }),
+            },
+            _ => Box::pin(async move {
+                execute_canister_call(ic_eth_rpc, service, json_rpc_payload, max_response_bytes)
+                    .await
+            }),
+        }
+    }
 }
 
 async fn execute_canister_call(
@@ -50,7 +113,7 @@ async fn execute_canister_call(
     service: RpcService,
     json_rpc_payload: String,
     max_response_bytes: u64,
-) -> Result<Value, ic_web3_rs::Error> {
+) -> Result<Value, ProviderError> {
     let (result,): (Result<String, cketh_common::eth_rpc::RpcError>,) = call_with_payment128(
         ic_eth_rpc,
         "request",
@@ -58,9 +121,7 @@ async fn execute_canister_call(
         MAX_CYCLES,
     )
     .await
-    .map_err(|(code, msg)| {
-        ic_web3_rs::Error::Transport(TransportError::Message(format!("{:?}: {}", code, msg)))
-    })?;
+    .map_err(|(code, msg)| ProviderError::Rejected(Reject::new(code, msg)))?;
 
     let result = result.map_err(|err| {
         ic_web3_rs::Error::Transport(TransportError::Message(format!(
@@ -69,13 +130,14 @@ async fn execute_canister_call(
         )))
     })?;
 
-    let output: Output = serde_json::from_str(&result).unwrap();
+    let output: Output = serde_json::from_str(&result)
+        .map_err(|err| ic_web3_rs::Error::InvalidResponse(err.to_string()))?;
 
     match output {
         Output::Success(success) => Ok(success.result),
-        Output::Failure(failure) => Err(ic_web3_rs::Error::Transport(TransportError::Message(
-            failure.error.message,
-        ))),
+        Output::Failure(failure) => {
+            Err(ic_web3_rs::Error::Transport(TransportError::Message(failure.error.message)).into())
+        }
     }
 }
 
@@ -103,7 +165,7 @@ async fn send_raw_tx(
     source: RpcServices,
     config: Option<RpcConfig>,
     raw_tx: Vec<u8>,
-) -> Result<Value, ic_web3_rs::Error> {
+) -> Result<Value, ProviderError> {
     let (result,): (MultiRpcResult<SendRawTransactionResult>,) = call_with_payment128(
         evm_rpc_canister,
         "eth_sendRawTransaction",
@@ -111,23 +173,29 @@ async fn send_raw_tx(
         MAX_CYCLES,
     )
     .await
-    .map_err(|(code, msg)| {
-        ic_web3_rs::Error::Transport(TransportError::Message(format!("{:?}: {}", code, msg)))
-    })?;
-
-    let MultiRpcResult::Consistent(result) = result else {
-        unreachable!("Should be consistent result (or you need to implement handling of inconsistent results, idk, I'm not your mom)")
+    .map_err(|(code, msg)| ProviderError::Rejected(Reject::new(code, msg)))?;
+
+    // the consensus between the providers is handled by `MultiProviderTransport`,
+    // so the EVM RPC canister is called with a single service
+    let result = match result {
+        MultiRpcResult::Consistent(result) => result,
+        MultiRpcResult::Inconsistent(results) => {
+            return Err(ic_web3_rs::Error::InvalidResponse(format!(
+                "inconsistent results: {:?}",
+                results
+            ))
+            .into())
+        }
     };
 
-    result
-        .map(|val| {
-            if let SendRawTransactionResult::Ok = val {
-                Value::String(format!("{:#?}", H256::from_slice(&keccak256(&raw_tx))))
-            } else {
-                unreachable!("Should be a hash")
-            }
-        })
-        .map_err(|err| ic_web3_rs::Error::InvalidResponse(format!("{:?}", err)))
+    match result {
+        Ok(SendRawTransactionResult::Ok) => Ok(Value::String(format!(
+            "{:#?}",
+            H256::from_slice(&keccak256(&raw_tx))
+        ))),
+        Ok(result) => Err(ic_web3_rs::Error::InvalidResponse(format!("{:?}", result)).into()),
+        Err(err) => Err(ic_web3_rs::Error::InvalidResponse(format!("{:?}", err)).into()),
+    }
 }
 
 impl Transport for EVMCanisterTransport {
@@ -140,49 +208,8 @@ impl Transport for EVMCanisterTransport {
     }
 
     fn send(&self, _: RequestId, call: Call, _: CallOptions) -> Self::Out {
-        let service: RpcService = RpcService::Custom(RpcApi {
-            url: self.rpc_url.clone(),
-            headers: None,
-        });
-
-        let json_rpc_payload = serde_json::to_string(&Request::Single(call.clone())).unwrap();
-
-        let ic_eth_rpc = self.evm_rpc_canister;
-        let max_response_bytes = self.max_response_bytes;
-
-        match call {
-            Call::MethodCall(method_call) => match method_call.method.as_str() {
-                "eth_sendRawTransaction" => {
-                    let Params::Array(ref arr) = method_call.params else {
-                        unreachable!()
-                    };
-
-                    let raw_tx = hex::decode(&arr[0].as_str().unwrap()[2..])
-                        .expect("should be able to parse");
-
-                    Box::pin(send_raw_tx(
-                        ic_eth_rpc,
-                        RpcServices::Custom {
-                            chain_id: 5,
-                            services: vec![RpcApi {
-                                url: self.rpc_url.clone(),
-                                headers: None,
-                            }],
-                        },
-                        None,
-                        raw_tx,
-                    ))
-                }
-                _ => Box::pin(async move {
-                    execute_canister_call(ic_eth_rpc, service, json_rpc_payload, max_response_bytes)
-                        .await
-                }),
-            },
-            _ => Box::pin(async move {
-                execute_canister_call(ic_eth_rpc, service, json_rpc_payload, max_response_bytes)
-                    .await
-            }),
-        }
+        let future = self.send_call(call);
+        Box::pin(async move { future.await.map_err(ic_web3_rs::Error::from) })
     }
 
     fn set_max_response_bytes(&mut self, v: u64) {
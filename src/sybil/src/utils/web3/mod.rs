@@ -3,31 +3,41 @@ use candid::{CandidType, Nat, Principal};
 use ic_web3_rs::{
     api::Eth,
     contract::{tokens::Tokenizable, Contract, Options},
-    ethabi::{Token, TopicFilter},
+    ethabi::{self, Token, Topic, TopicFilter},
     ic::KeyInfo,
+    signing::keccak256,
     transports::ic_http::ICHttp,
     types::{
-        BlockNumber, FilterBuilder, Log, Transaction, TransactionId, TransactionReceipt, H160,
-        H256, U256,
+        BlockId, BlockNumber, Bytes, CallRequest, FilterBuilder, Log, Transaction, TransactionId,
+        TransactionReceipt, H160, H256, U256,
     },
     Transport, Web3,
 };
 use serde::Deserialize;
-use std::str::FromStr;
+use std::{str::FromStr, time::Duration};
 use thiserror::Error;
 
-use crate::retry_until_success;
+use crate::types::rpc::{RpcCfg, RpcTransport};
 
 use super::{
     address::{self, AddressError},
     nat, processors,
+    retry::{Attempts, RetryPolicy},
+    sleep,
 };
 
 pub const SUCCESSFUL_TX_STATUS: u64 = 1;
 pub const ECDSA_SIGN_CYCLES: u64 = 23_000_000_000;
 pub const ERC20_TRANSFER_METHOD: &str = "transfer";
+pub const ERC20_BALANCE_OF_SIGNATURE: &str = "balanceOf(address)";
+const TX_RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const TX_RECEIPT_MAX_ATTEMPTS: u32 = 24;
 
 mod evm_canister_transport;
+mod multi_provider_transport;
+
+use evm_canister_transport::EVMCanisterTransport;
+pub use multi_provider_transport::{MultiProviderTransport, ProviderTransport};
 
 #[derive(Error, Debug, CandidType, Deserialize)]
 pub enum Web3Error {
@@ -69,6 +79,10 @@ pub enum Web3Error {
     UnableToCallContract(String),
     #[error("Unable to create contract: {0}")]
     UnableToCreateContract(String),
+    #[error("Unable to get storage: {0}")]
+    UnableToGetStorage(String),
+    #[error("Unable to get balance: {0}")]
+    UnableToGetBalance(String),
     #[error("Utils error: {0}")]
     UtilsError(String),
     #[error("From hex error: {0}")]
@@ -79,15 +93,36 @@ pub struct Web3Instance<T: Transport> {
     w3: Web3<T>,
 }
 
-pub fn instance(rpc_url: String, _evm_rpc_canister: Principal) -> Web3Instance<impl Transport> {
-    // Switch between EVMCanisterTransport(calls go through emv_rpc canister) and ICHttp (calls go straight to the rpc)
-
-    // Web3Instance::new(Web3::new(EVMCanisterTransport::new(
-    //     rpc_url,
-    //     evm_rpc_canister,
-    // )))
-
-    Web3Instance::new(Web3::new(ICHttp::new(&rpc_url, None).unwrap()))
+/// Web3 instance calling the providers with the transport and consensus policy of the config,
+/// the first url is the main provider. The transient rejects of the providers are retried
+/// by `retry_policy`, all the attempts are counted in `attempts`
+pub fn instance(
+    rpc_urls: Vec<String>,
+    rpc_cfg: &RpcCfg,
+    evm_rpc_canister: Principal,
+    retry_policy: RetryPolicy,
+    attempts: Attempts,
+) -> Web3Instance<MultiProviderTransport> {
+    let transport = rpc_cfg.transport();
+
+    let providers = rpc_urls
+        .into_iter()
+        .map(|rpc_url| match transport {
+            RpcTransport::Http => ProviderTransport::Http(
+                ICHttp::new(&rpc_url, None).expect("should be able to create transport"),
+            ),
+            RpcTransport::EvmRpcCanister => ProviderTransport::EvmRpcCanister(
+                EVMCanisterTransport::new(rpc_url, evm_rpc_canister, rpc_cfg.chain_id),
+            ),
+        })
+        .collect();
+
+    Web3Instance::new(Web3::new(MultiProviderTransport::new(
+        providers,
+        rpc_cfg.consensus(),
+        retry_policy,
+        attempts,
+    )))
 }
 
 impl<T: Transport> Web3Instance<T> {
@@ -108,28 +143,37 @@ impl<T: Transport> Web3Instance<T> {
         }
     }
 
+    /// Returns the logs matching the filter, `topics` are matched by position (topic0-topic3).
+    /// Logs are ordered by block number, transaction index and log index, removed logs are skipped
     pub async fn get_logs(
         &self,
-        from: Option<u64>,
-        to: Option<u64>,
-        topic: Option<H256>,
+        from: Option<BlockNumber>,
+        to: Option<BlockNumber>,
+        topics: [Option<H256>; 4],
         address: Option<H160>,
         block_hash: Option<H256>,
     ) -> Result<Vec<Log>, Web3Error> {
         let mut filter_builder = FilterBuilder::default();
 
         if let Some(from) = from {
-            filter_builder = filter_builder.from_block(BlockNumber::Number(from.into()));
+            filter_builder = filter_builder.from_block(from);
         }
 
         if let Some(to) = to {
-            filter_builder = filter_builder.from_block(BlockNumber::Number(to.into()));
+            filter_builder = filter_builder.to_block(to);
         }
 
-        if let Some(topic) = topic {
+        if topics.iter().any(Option::is_some) {
+            let [topic0, topic1, topic2, topic3] = topics.map(|topic| match topic {
+                Some(topic) => Topic::This(topic),
+                None => Topic::Any,
+            });
+
             filter_builder = filter_builder.topic_filter(TopicFilter {
-                topic0: ic_web3_rs::ethabi::Topic::This(topic),
-                ..Default::default()
+                topic0,
+                topic1,
+                topic2,
+                topic3,
             });
         }
 
@@ -141,24 +185,114 @@ impl<T: Transport> Web3Instance<T> {
             filter_builder = filter_builder.block_hash(block_hash);
         }
 
-        let logs = self
+        let filter = filter_builder.build();
+        let mut logs = self
             .eth()
-            .logs(filter_builder.build(), processors::transform_ctx())
+            .logs(filter, processors::transform_ctx())
             .await
             .map_err(|err| Web3Error::UnableToGetLogs(err.to_string()))?;
 
+        logs.retain(|log| log.removed != Some(true));
+        logs.sort_by_key(|log| (log.block_number, log.transaction_index, log.log_index));
+
         Ok(logs)
     }
 
+    pub async fn get_block_number(&self) -> Result<u64, Web3Error> {
+        self.eth()
+            .block_number(processors::transform_ctx())
+            .await
+            .map(|block_number| block_number.as_u64())
+            .map_err(|err| Web3Error::UnableToGetBlockNumber(err.to_string()))
+    }
+
+    /// Executes `eth_call` with the given call data, at the latest block by default
+    pub async fn call(
+        &self,
+        to: H160,
+        data: Vec<u8>,
+        block: Option<u64>,
+    ) -> Result<Vec<u8>, Web3Error> {
+        let req = CallRequest {
+            to: Some(to),
+            data: Some(Bytes(data)),
+            ..Default::default()
+        };
+
+        let result = self
+            .eth()
+            .call(
+                req,
+                block.map(|block| BlockId::Number(BlockNumber::Number(block.into()))),
+                processors::transform_ctx(),
+            )
+            .await
+            .map_err(|err| Web3Error::UnableToCallContract(err.to_string()))?;
+
+        Ok(result.0)
+    }
+
+    /// Reads the storage slot of the contract with `eth_getStorageAt`
+    pub async fn get_storage_at(
+        &self,
+        address: H160,
+        slot: U256,
+        block: Option<u64>,
+    ) -> Result<H256, Web3Error> {
+        self.eth()
+            .storage(
+                address,
+                slot,
+                block.map(|block| BlockNumber::Number(block.into())),
+                processors::transform_ctx(),
+            )
+            .await
+            .map_err(|err| Web3Error::UnableToGetStorage(err.to_string()))
+    }
+
+    /// Returns the native balance of the address
+    pub async fn get_balance(&self, address: H160, block: Option<u64>) -> Result<U256, Web3Error> {
+        self.eth()
+            .balance(
+                address,
+                block.map(|block| BlockNumber::Number(block.into())),
+                processors::transform_ctx(),
+            )
+            .await
+            .map_err(|err| Web3Error::UnableToGetBalance(err.to_string()))
+    }
+
+    /// Returns the ERC20 token balance of the address
+    pub async fn get_erc20_balance(
+        &self,
+        token: H160,
+        address: H160,
+        block: Option<u64>,
+    ) -> Result<U256, Web3Error> {
+        let mut data = keccak256(ERC20_BALANCE_OF_SIGNATURE.as_bytes())[..4].to_vec();
+        data.extend(ethabi::encode(&[Token::Address(address)]));
+
+        let output = self.call(token, data, block).await?;
+        if output.len() != 32 {
+            return Err(Web3Error::UnableToDecodeOutput(format!(
+                "expected 32 bytes, got {}",
+                output.len()
+            )));
+        }
+
+        Ok(U256::from_big_endian(&output))
+    }
+
     pub async fn get_tx(&self, tx_hash: &str) -> Result<Transaction, Web3Error> {
         let tx_hash =
             H256::from_str(tx_hash).map_err(|err| Web3Error::FromHexError(err.to_string()))?;
 
-        let result = retry_until_success!(self
+        let result = self
             .eth()
-            .transaction(TransactionId::from(tx_hash), processors::transform_ctx_tx()))
-        .map_err(|err| Web3Error::UnableToGetTxReceipt(err.to_string()))?
-        .ok_or(Web3Error::TxNotFound)?;
+            .transaction(TransactionId::from(tx_hash), processors::transform_ctx_tx())
+            .await
+            .map_err(|err| Web3Error::UnableToGetTxReceipt(err.to_string()))?
+            .ok_or(Web3Error::TxNotFound)?;
 
         Ok(result)
     }
@@ -167,30 +301,51 @@ impl<T: Transport> Web3Instance<T> {
         let tx_hash =
             H256::from_str(tx_hash).map_err(|err| Web3Error::FromHexError(err.to_string()))?;
 
-        Ok(retry_until_success!(self
+        Ok(self
             .eth()
-            .transaction_receipt(tx_hash, processors::transform_ctx_tx_with_logs()))
-        .map_err(|err| Web3Error::UnableToGetTxReceipt(err.to_string()))?
-        .ok_or(Web3Error::TxNotFound)?)
+            .transaction_receipt(tx_hash, processors::transform_ctx_tx_with_logs())
+            .await
+            .map_err(|err| Web3Error::UnableToGetTxReceipt(err.to_string()))?
+            .ok_or(Web3Error::TxNotFound)?)
+    }
+
+    /// Polls the receipt until the transaction is mined, fails if it has failed or isn't mined in time
+    pub async fn wait_for_success(&self, tx_hash: &str) -> Result<TransactionReceipt, Web3Error> {
+        for _ in 0..TX_RECEIPT_MAX_ATTEMPTS {
+            match self.get_tx_receipt(tx_hash).await {
+                Ok(receipt) => match receipt.status {
+                    Some(status) if status.as_u64() == SUCCESSFUL_TX_STATUS => return Ok(receipt),
+                    Some(_) => return Err(Web3Error::TxHasFailed),
+                    None => {}
+                },
+                Err(Web3Error::TxNotFound) => {}
+                Err(err) => return Err(err),
+            }
+
+            sleep(TX_RECEIPT_POLL_INTERVAL).await;
+        }
+
+        Err(Web3Error::TxTimeout)
     }
 
     pub async fn get_gas_price(&self) -> Result<U256, Web3Error> {
-        let gas_price =
-            match retry_until_success!(self.eth().gas_price(processors::transform_ctx())) {
-                Ok(gas_price) => gas_price,
-                Err(e) => Err(Web3Error::UnableToGetGasPrice(e.to_string()))?,
-            };
+        let gas_price = match self.eth().gas_price(processors::transform_ctx()).await {
+            Ok(gas_price) => gas_price,
+            Err(e) => Err(Web3Error::UnableToGetGasPrice(e.to_string()))?,
+        };
 
         Ok(gas_price)
     }
 
     pub async fn get_nonce(&self, account_address: &str) -> Result<U256, Web3Error> {
-        let nonce = match retry_until_success!(self.eth().transaction_count(
-            H160::from_str(account_address)
-                .map_err(|err| Web3Error::InvalidAddressFormat(err.to_string()))?,
-            None,
-            processors::transform_ctx()
-        )) {
+        let account_address = H160::from_str(account_address)
+            .map_err(|err| Web3Error::InvalidAddressFormat(err.to_string()))?;
+
+        let nonce = match self
+            .eth()
+            .transaction_count(account_address, None, processors::transform_ctx())
+            .await
+        {
             Ok(nonce) => nonce,
             Err(e) => Err(Web3Error::UnableToGetNonce(e.to_string()))?,
         };
@@ -0,0 +1,522 @@
+use ic_cdk::api::call::RejectionCode;
+use ic_web3_rs::{
+    error::TransportError,
+    futures::future::{join_all, BoxFuture},
+    signing::keccak256,
+    transports::ic_http::{CallOptions, ICHttp},
+    types::U256,
+    RequestId, Transport,
+};
+use jsonrpc_core::{Call, Params};
+use serde_json::Value;
+
+use super::evm_canister_transport::EVMCanisterTransport;
+use crate::{
+    types::rpc::ConsensusPolicy,
+    utils::retry::{retry_with_attempts, Attempts, Reject, RetryPolicy, Retryable},
+};
+
+const RAW_TX_METHOD: &str = "eth_sendRawTransaction";
+/// Errors of a raw transaction which is already submitted through another provider
+const KNOWN_TX_ERRORS: [&str; 3] = ["already known", "known transaction", "already imported"];
+/// Methods returning quantities which routinely differ across the providers,
+/// the median of the responses is taken instead of the exact match, see `MAX_QUANTITY_METHODS`
+const QUANTITY_METHODS: [&str; 5] = [
+    "eth_blockNumber",
+    "eth_gasPrice",
+    "eth_getTransactionCount",
+    "eth_estimateGas",
+    "eth_maxPriorityFeePerGas",
+];
+/// Quantities taken as the maximum of the responses,
+/// a nonce lower than the highest one would collide with a pending transaction
+const MAX_QUANTITY_METHODS: [&str; 1] = ["eth_getTransactionCount"];
+
+/// Error of a provider call, the rejects keep their code for the retries and the health of the rpc wrappers
+#[derive(Debug)]
+pub enum ProviderError {
+    Rejected(Reject),
+    Failed(ic_web3_rs::Error),
+}
+
+impl ProviderError {
+    /// `ICHttp` keeps only the debug output of the reject, e.g. `(SysTransient, "Timeout expired")`,
+    /// so its code is parsed back here, once, instead of matching the messages of the rejects
+    fn from_ic_http(err: ic_web3_rs::Error) -> Self {
+        match &err {
+            ic_web3_rs::Error::Transport(TransportError::Message(msg)) => match ic_http_reject(msg)
+            {
+                Some(reject) => ProviderError::Rejected(reject),
+                None => ProviderError::Failed(err),
+            },
+            _ => ProviderError::Failed(err),
+        }
+    }
+}
+
+impl From<ic_web3_rs::Error> for ProviderError {
+    fn from(err: ic_web3_rs::Error) -> Self {
+        ProviderError::Failed(err)
+    }
+}
+
+impl From<ProviderError> for ic_web3_rs::Error {
+    fn from(err: ProviderError) -> Self {
+        match err {
+            ProviderError::Rejected(reject) => reject.into(),
+            ProviderError::Failed(err) => err,
+        }
+    }
+}
+
+impl Retryable for ProviderError {
+    fn is_retryable(&self) -> bool {
+        matches!(self, ProviderError::Rejected(reject) if reject.is_retryable())
+    }
+}
+
+fn ic_http_reject(msg: &str) -> Option<Reject> {
+    let (code, msg) = msg.strip_prefix('(')?.strip_suffix(')')?.split_once(", ")?;
+
+    let code = match code {
+        "NoError" => RejectionCode::NoError,
+        "SysFatal" => RejectionCode::SysFatal,
+        "SysTransient" => RejectionCode::SysTransient,
+        "DestinationInvalid" => RejectionCode::DestinationInvalid,
+        "CanisterReject" => RejectionCode::CanisterReject,
+        "CanisterError" => RejectionCode::CanisterError,
+        "Unknown" => RejectionCode::Unknown,
+        _ => return None,
+    };
+
+    let msg = serde_json::from_str::<String>(msg).unwrap_or_else(|_| msg.to_string());
+    Some(Reject::new(code, msg))
+}
+
+#[derive(Clone, Debug)]
+pub enum ProviderTransport {
+    Http(ICHttp),
+    EvmRpcCanister(EVMCanisterTransport),
+}
+
+impl ProviderTransport {
+    /// Sends the call retrying the transient rejects, every attempt is counted in `attempts`,
+    /// the reject the call has finally failed with is kept there
+    fn send(
+        &self,
+        id: RequestId,
+        call: Call,
+        options: CallOptions,
+        retry_policy: RetryPolicy,
+        attempts: Attempts,
+    ) -> BoxFuture<'static, Result<Value, ic_web3_rs::Error>> {
+        let provider = self.clone();
+
+        Box::pin(async move {
+            if attempts.is_exhausted() {
+                return Err(ic_web3_rs::Error::Transport(TransportError::Message(
+                    "attempts of the source are exhausted".to_string(),
+                )));
+            }
+
+            retry_with_attempts(&retry_policy, &attempts, || {
+                provider.send_once(id, call.clone(), options.clone())
+            })
+            .await
+            .map_err(|err| {
+                if let ProviderError::Rejected(reject) = &err {
+                    attempts.add_reject(reject.clone());
+                }
+
+                err.into()
+            })
+        })
+    }
+
+    fn send_once(
+        &self,
+        id: RequestId,
+        call: Call,
+        options: CallOptions,
+    ) -> BoxFuture<'static, Result<Value, ProviderError>> {
+        match self {
+            ProviderTransport::Http(transport) => {
+                let future = transport.send(id, call, options);
+                Box::pin(async move { future.await.map_err(ProviderError::from_ic_http) })
+            }
+            ProviderTransport::EvmRpcCanister(transport) => transport.send_call(call),
+        }
+    }
+}
+
+/// Sends the requests to several providers of the same chain and accepts the response by the consensus policy
+#[derive(Clone, Debug)]
+pub struct MultiProviderTransport {
+    providers: Vec<ProviderTransport>,
+    consensus: ConsensusPolicy,
+    retry_policy: RetryPolicy,
+    attempts: Attempts,
+}
+
+impl MultiProviderTransport {
+    pub fn new(
+        providers: Vec<ProviderTransport>,
+        consensus: ConsensusPolicy,
+        retry_policy: RetryPolicy,
+        attempts: Attempts,
+    ) -> Self {
+        assert!(!providers.is_empty(), "at least one provider is required");
+
+        Self {
+            providers,
+            consensus,
+            retry_policy,
+            attempts,
+        }
+    }
+}
+
+impl Transport for MultiProviderTransport {
+    type Out = BoxFuture<'static, Result<Value, ic_web3_rs::Error>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        match &self.providers[0] {
+            ProviderTransport::Http(transport) => transport.prepare(method, params),
+            ProviderTransport::EvmRpcCanister(transport) => transport.prepare(method, params),
+        }
+    }
+
+    fn send(&self, id: RequestId, call: Call, options: CallOptions) -> Self::Out {
+        let (method, params) = match &call {
+            Call::MethodCall(method_call) => {
+                (method_call.method.clone(), Some(method_call.params.clone()))
+            }
+            _ => (String::new(), None),
+        };
+
+        // a raw transaction is not resubmitted, its transient reject doesn't tell whether it is sent
+        let retry_policy = if method == RAW_TX_METHOD {
+            RetryPolicy {
+                max_retries: Some(0),
+                ..Default::default()
+            }
+        } else {
+            self.retry_policy
+        };
+
+        let futures = self
+            .providers
+            .iter()
+            .map(|provider| {
+                provider.send(
+                    id,
+                    call.clone(),
+                    options.clone(),
+                    retry_policy,
+                    self.attempts.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let consensus = self.consensus;
+
+        Box::pin(async move {
+            if method == RAW_TX_METHOD {
+                let tx_hash = params.as_ref().and_then(raw_tx_hash);
+                return match consensus {
+                    // a transaction is submitted once, the other providers would reject it as known
+                    ConsensusPolicy::First => first_success(futures, tx_hash).await,
+                    ConsensusPolicy::Majority | ConsensusPolicy::All => {
+                        resolve_raw_tx(consensus, tx_hash, join_all(futures).await)
+                    }
+                };
+            }
+
+            match consensus {
+                ConsensusPolicy::First => first_success(futures, None).await,
+                ConsensusPolicy::Majority | ConsensusPolicy::All => {
+                    resolve_consensus(consensus, &method, join_all(futures).await)
+                }
+            }
+        })
+    }
+
+    fn set_max_response_bytes(&mut self, bytes: u64) {
+        for provider in &mut self.providers {
+            match provider {
+                ProviderTransport::Http(transport) => transport.set_max_response_bytes(bytes),
+                ProviderTransport::EvmRpcCanister(transport) => {
+                    transport.set_max_response_bytes(bytes)
+                }
+            }
+        }
+    }
+}
+
+/// Calls the providers in order until the first successful response, the futures are lazy,
+/// so the next provider is called only if the previous one has failed.
+/// With `tx_hash` set, the known transaction errors are taken as the successful submission
+async fn first_success(
+    futures: Vec<BoxFuture<'static, Result<Value, ic_web3_rs::Error>>>,
+    tx_hash: Option<String>,
+) -> Result<Value, ic_web3_rs::Error> {
+    let mut errors = vec![];
+    for future in futures {
+        match future.await {
+            Ok(value) => return Ok(value),
+            Err(err) => match &tx_hash {
+                Some(tx_hash) if is_known_tx(&err) => return Ok(Value::from(tx_hash.clone())),
+                _ => errors.push(err),
+            },
+        }
+    }
+
+    Err(inconsistent(errors.iter().map(|err| err.to_string())))
+}
+
+fn is_known_tx(err: &ic_web3_rs::Error) -> bool {
+    let err = err.to_string().to_lowercase();
+    KNOWN_TX_ERRORS.iter().any(|known| err.contains(known))
+}
+
+/// Hash of the raw transaction in the first param
+fn raw_tx_hash(params: &Params) -> Option<String> {
+    let Params::Array(params) = params else {
+        return None;
+    };
+
+    let raw_tx = hex::decode(params.first()?.as_str()?.trim_start_matches("0x")).ok()?;
+    Some(format!("0x{}", hex::encode(keccak256(&raw_tx))))
+}
+
+fn required_responses(consensus: ConsensusPolicy, total: usize) -> usize {
+    match consensus {
+        ConsensusPolicy::First => 1,
+        ConsensusPolicy::Majority => total / 2 + 1,
+        ConsensusPolicy::All => total,
+    }
+}
+
+/// Accepts the raw transaction submitted to all the providers if enough of them have accepted it,
+/// the known transaction errors are counted as the acceptance
+fn resolve_raw_tx(
+    consensus: ConsensusPolicy,
+    tx_hash: Option<String>,
+    results: Vec<Result<Value, ic_web3_rs::Error>>,
+) -> Result<Value, ic_web3_rs::Error> {
+    let required = required_responses(consensus, results.len());
+
+    let accepted = results
+        .iter()
+        .filter(|result| match result {
+            Ok(_) => true,
+            Err(err) => tx_hash.is_some() && is_known_tx(err),
+        })
+        .count();
+
+    if accepted >= required {
+        let value = match tx_hash {
+            Some(tx_hash) => Value::from(tx_hash),
+            None => results
+                .into_iter()
+                .find_map(Result::ok)
+                .expect("at least one provider should accept the transaction"),
+        };
+
+        return Ok(value);
+    }
+
+    Err(inconsistent(results.iter().map(|result| match result {
+        Ok(value) => value.to_string(),
+        Err(err) => err.to_string(),
+    })))
+}
+
+/// Picks the response agreed by the providers, errors are counted as disagreement.
+/// The quantities of `QUANTITY_METHODS` agree if enough providers respond,
+/// the median is taken, or the maximum for `MAX_QUANTITY_METHODS`
+fn resolve_consensus(
+    consensus: ConsensusPolicy,
+    method: &str,
+    results: Vec<Result<Value, ic_web3_rs::Error>>,
+) -> Result<Value, ic_web3_rs::Error> {
+    let required = required_responses(consensus, results.len());
+
+    if QUANTITY_METHODS.contains(&method) {
+        let mut quantities = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .filter_map(|value| {
+                U256::from_str_radix(value.as_str()?.trim_start_matches("0x"), 16).ok()
+            })
+            .collect::<Vec<_>>();
+
+        if quantities.len() >= required {
+            quantities.sort();
+            let quantity = if MAX_QUANTITY_METHODS.contains(&method) {
+                quantities[quantities.len() - 1]
+            } else {
+                quantities[(quantities.len() - 1) / 2]
+            };
+
+            return Ok(Value::from(format!("{quantity:#x}")));
+        }
+    } else {
+        let mut groups: Vec<(&Value, usize)> = vec![];
+        for value in results.iter().filter_map(|result| result.as_ref().ok()) {
+            match groups.iter_mut().find(|(group, _)| *group == value) {
+                Some((_, count)) => *count += 1,
+                None => groups.push((value, 1)),
+            }
+        }
+
+        if let Some((value, _)) = groups.iter().find(|(_, count)| *count >= required) {
+            return Ok((*value).clone());
+        }
+    }
+
+    Err(inconsistent(results.iter().map(|result| match result {
+        Ok(value) => value.to_string(),
+        Err(err) => err.to_string(),
+    })))
+}
+
+fn inconsistent(results: impl Iterator<Item = String>) -> ic_web3_rs::Error {
+    ic_web3_rs::Error::Transport(TransportError::Message(format!(
+        "providers are inconsistent: [{}]",
+        results.collect::<Vec<_>>().join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err() -> Result<Value, ic_web3_rs::Error> {
+        Err(ic_web3_rs::Error::Unreachable)
+    }
+
+    #[test]
+    fn test_majority() {
+        let results = vec![Ok(Value::from("0x1")), Ok(Value::from("0x1")), err()];
+        assert_eq!(
+            resolve_consensus(ConsensusPolicy::Majority, "eth_call", results).unwrap(),
+            Value::from("0x1")
+        );
+
+        let results = vec![Ok(Value::from("0x1")), Ok(Value::from("0x2")), err()];
+        assert!(resolve_consensus(ConsensusPolicy::Majority, "eth_call", results).is_err());
+    }
+
+    #[test]
+    fn test_quantities() {
+        let results = vec![
+            Ok(Value::from("0x10")),
+            Ok(Value::from("0xf")),
+            Ok(Value::from("0x11")),
+        ];
+        assert_eq!(
+            resolve_consensus(ConsensusPolicy::All, "eth_blockNumber", results).unwrap(),
+            Value::from("0x10")
+        );
+
+        let results = vec![Ok(Value::from("0x10")), Ok(Value::from("0xf")), err()];
+        assert_eq!(
+            resolve_consensus(ConsensusPolicy::Majority, "eth_gasPrice", results).unwrap(),
+            Value::from("0xf")
+        );
+
+        let results = vec![Ok(Value::from("0x10")), err(), err()];
+        assert!(resolve_consensus(ConsensusPolicy::Majority, "eth_gasPrice", results).is_err());
+
+        // the highest nonce is taken
+        let results = vec![
+            Ok(Value::from("0x5")),
+            Ok(Value::from("0x7")),
+            Ok(Value::from("0x6")),
+        ];
+        assert_eq!(
+            resolve_consensus(
+                ConsensusPolicy::Majority,
+                "eth_getTransactionCount",
+                results
+            )
+            .unwrap(),
+            Value::from("0x7")
+        );
+    }
+
+    #[test]
+    fn test_raw_tx() {
+        let tx_hash = Some("0x01".to_string());
+        let known = || -> Result<Value, ic_web3_rs::Error> {
+            Err(ic_web3_rs::Error::Transport(TransportError::Message(
+                "(SysFatal, \"already known\")".to_string(),
+            )))
+        };
+
+        let results = vec![Ok(Value::from("0x01")), known(), err()];
+        assert_eq!(
+            resolve_raw_tx(ConsensusPolicy::Majority, tx_hash.clone(), results).unwrap(),
+            Value::from("0x01")
+        );
+
+        let results = vec![Ok(Value::from("0x01")), known(), err()];
+        assert!(resolve_raw_tx(ConsensusPolicy::All, tx_hash.clone(), results).is_err());
+
+        let results = vec![Ok(Value::from("0x01")), err(), err()];
+        assert!(resolve_raw_tx(ConsensusPolicy::Majority, tx_hash, results).is_err());
+
+        // the known errors can't be attributed to the transaction without its hash
+        let results = vec![Ok(Value::from("0x01")), known(), err()];
+        assert!(resolve_raw_tx(ConsensusPolicy::Majority, None, results).is_err());
+    }
+
+    #[test]
+    fn test_raw_tx_hash() {
+        let params = Params::Array(vec![Value::from("0x")]);
+        assert_eq!(
+            raw_tx_hash(&params).unwrap(),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert!(raw_tx_hash(&Params::None).is_none());
+
+        let known = ic_web3_rs::Error::Transport(TransportError::Message(
+            "(SysFatal, \"already known\")".to_string(),
+        ));
+        assert!(is_known_tx(&known));
+        assert!(!is_known_tx(&ic_web3_rs::Error::Unreachable));
+    }
+
+    #[test]
+    fn test_ic_http_reject() {
+        let err = ic_web3_rs::Error::Transport(TransportError::Message(
+            "(SysTransient, \"Timeout expired\")".to_string(),
+        ));
+        let ProviderError::Rejected(reject) = ProviderError::from_ic_http(err) else {
+            panic!("should be a reject");
+        };
+        assert_eq!(
+            reject,
+            Reject::new(RejectionCode::SysTransient, "Timeout expired".to_string())
+        );
+
+        let err = ic_web3_rs::Error::Transport(TransportError::Message(
+            "SysTransient timeout in the rpc response".to_string(),
+        ));
+        assert!(!ProviderError::from_ic_http(err).is_retryable());
+        assert!(!ProviderError::from_ic_http(ic_web3_rs::Error::Unreachable).is_retryable());
+    }
+
+    #[test]
+    fn test_all() {
+        let results = vec![Ok(Value::from("0x1")), Ok(Value::from("0x1"))];
+        assert_eq!(
+            resolve_consensus(ConsensusPolicy::All, "eth_call", results).unwrap(),
+            Value::from("0x1")
+        );
+
+        let results = vec![Ok(Value::from("0x1")), err()];
+        assert!(resolve_consensus(ConsensusPolicy::All, "eth_call", results).is_err());
+    }
+}
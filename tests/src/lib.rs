@@ -3,6 +3,8 @@ mod balances;
 #[cfg(test)]
 mod default_feeds;
 #[cfg(test)]
+mod signer;
+#[cfg(test)]
 mod whitelist;
 
 mod utils;
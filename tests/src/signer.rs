@@ -0,0 +1,66 @@
+use scopeguard::defer;
+
+use crate::{clear_state, pre_test, sybil_execute};
+
+const SCHEMES: [&str; 3] = ["Ecdsa", "SchnorrBip340", "Ed25519"];
+
+pub fn derive_signer_key(req: &str, scheme: &str) -> Result<String, String> {
+    sybil_execute(
+        "derive_signer_key",
+        Some(&format!("({req}, variant {{ {scheme} }})")),
+    )
+}
+
+pub fn get_signer_public_key(req: &str, scheme: &str) -> Result<String, String> {
+    sybil_execute(
+        "get_signer_public_key",
+        Some(&format!("({req}, variant {{ {scheme} }})")),
+    )
+}
+
+pub fn get_signer_address(req: &str) -> Result<String, String> {
+    sybil_execute("get_signer_address", Some(&format!("({req})")))
+}
+
+#[test]
+fn test_derive_canister_signer_keys() {
+    pre_test().expect("failed to run pre tests");
+    defer!(clear_state().expect("failed to clear state"));
+
+    let req = "variant { Canister }";
+
+    for scheme in SCHEMES {
+        let public_key = derive_signer_key(req, scheme).expect("failed to derive signer key");
+        assert_eq!(
+            get_signer_public_key(req, scheme),
+            Ok(public_key),
+            "derived {scheme} public key is not returned by the query"
+        );
+    }
+
+    assert!(
+        get_signer_address(req).is_ok(),
+        "ecdsa address is not derived"
+    );
+}
+
+#[test]
+fn test_derive_unknown_owner_signer_key_error() {
+    pre_test().expect("failed to run pre tests");
+    defer!(clear_state().expect("failed to clear state"));
+
+    let req = "variant { Owner = \"0x0000000000000000000000000000000000000001\" }";
+
+    assert!(
+        derive_signer_key(req, "Ed25519")
+            .unwrap_err()
+            .contains("Owner has no balance"),
+        "should fail with unknown owner"
+    );
+    assert!(
+        get_signer_public_key(req, "Ed25519")
+            .unwrap_err()
+            .contains("not derived"),
+        "should fail with not derived key"
+    );
+}